@@ -0,0 +1,92 @@
+use std::path::Path;
+use std::str::FromStr;
+use std::time::Duration;
+
+use tonic::codec::CompressionEncoding;
+
+/// Compression accepted for the transaction stream responses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Compression {
+    Gzip,
+    Zstd,
+}
+
+impl Compression {
+    pub(crate) fn encoding(self) -> CompressionEncoding {
+        match self {
+            Compression::Gzip => CompressionEncoding::Gzip,
+            Compression::Zstd => CompressionEncoding::Zstd,
+        }
+    }
+}
+
+impl FromStr for Compression {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "gzip" => Ok(Compression::Gzip),
+            "zstd" => Ok(Compression::Zstd),
+            _ => Err(format!("Unknown compression: {}", s)),
+        }
+    }
+}
+
+/// Transport settings of the gRPC connection to the transaction stream.
+/// Settings left to `None` keep the tonic and server defaults.
+#[derive(Debug, Clone, Default)]
+pub struct ListenerConfig {
+    pub compression: Option<Compression>,
+    /// Largest response accepted, tonic defaults to 4 MiB which large blocks can exceed
+    pub max_decoding_message_size: Option<usize>,
+    /// Interval of the HTTP/2 keepalive pings
+    pub keepalive_interval: Option<Duration>,
+    pub keepalive_timeout: Option<Duration>,
+    pub connect_timeout: Option<Duration>,
+    /// PEM encoded CA certificates trusted on top of the system roots, e.g. for a corporate proxy
+    pub ca_certificates: Option<Vec<u8>>,
+    /// Number of transactions per response of the stream
+    pub batch_size: Option<u64>,
+}
+
+impl ListenerConfig {
+    pub fn with_ca_certificates_file(mut self, path: impl AsRef<Path>) -> std::io::Result<Self> {
+        self.ca_certificates = Some(std::fs::read(path)?);
+        Ok(self)
+    }
+
+    /// Reads the settings from the `LISTENER_*` environment variables, unset ones keep their default:
+    /// `LISTENER_COMPRESSION` (gzip or zstd), `LISTENER_MAX_MESSAGE_SIZE` (bytes),
+    /// `LISTENER_KEEPALIVE_INTERVAL`, `LISTENER_KEEPALIVE_TIMEOUT`, `LISTENER_CONNECT_TIMEOUT` (seconds),
+    /// `LISTENER_CA_CERTIFICATES` (path to a PEM file) and `LISTENER_BATCH_SIZE`.
+    pub fn from_env() -> Result<Self, Box<dyn std::error::Error>> {
+        let mut config = ListenerConfig {
+            compression: env_var("LISTENER_COMPRESSION")?,
+            max_decoding_message_size: env_var("LISTENER_MAX_MESSAGE_SIZE")?,
+            keepalive_interval: env_var("LISTENER_KEEPALIVE_INTERVAL")?.map(Duration::from_secs),
+            keepalive_timeout: env_var("LISTENER_KEEPALIVE_TIMEOUT")?.map(Duration::from_secs),
+            connect_timeout: env_var("LISTENER_CONNECT_TIMEOUT")?.map(Duration::from_secs),
+            ca_certificates: None,
+            batch_size: env_var("LISTENER_BATCH_SIZE")?,
+        };
+
+        if let Ok(path) = std::env::var("LISTENER_CA_CERTIFICATES") {
+            config = config.with_ca_certificates_file(path)?;
+        }
+
+        Ok(config)
+    }
+}
+
+fn env_var<T: FromStr>(name: &str) -> Result<Option<T>, String>
+where
+    T::Err: std::fmt::Display,
+{
+    match std::env::var(name) {
+        Ok(value) => value
+            .parse()
+            .map(Some)
+            .map_err(|e| format!("Invalid {}: {}", name, e)),
+        Err(_) => Ok(None),
+    }
+}
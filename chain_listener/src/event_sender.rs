@@ -0,0 +1,67 @@
+use std::sync::Arc;
+
+use tokio::sync::broadcast;
+use tokio::sync::mpsc::error::SendError;
+use tokio::sync::mpsc::{Sender, UnboundedSender, WeakSender};
+
+/// Sending half of the channel the listener pushes events into.
+/// With a bounded channel the listener stops reading from its source while the channel is full,
+/// so a slow consumer slows the listener down instead of growing the queue without limit.
+/// A broadcast channel never blocks the listener, receivers that fall behind miss events instead.
+#[derive(Debug)]
+pub enum EventSender<T> {
+    Unbounded(UnboundedSender<T>),
+    Bounded(Sender<T>),
+    Broadcast(broadcast::Sender<Arc<T>>),
+}
+
+impl<T> EventSender<T> {
+    /// Waits for room in the channel when it is bounded.
+    pub async fn send(&self, value: T) -> Result<(), SendError<T>> {
+        match self {
+            EventSender::Unbounded(sender) => sender.send(value),
+            EventSender::Bounded(sender) => sender.send(value).await,
+            EventSender::Broadcast(sender) => {
+                // no subscriber at the moment, new ones may still come
+                let _ = sender.send(Arc::new(value));
+                Ok(())
+            }
+        }
+    }
+
+    /// Weak handle used to watch the queue depth of a bounded channel.
+    pub(crate) fn downgrade(&self) -> Option<WeakSender<T>> {
+        match self {
+            EventSender::Bounded(sender) => Some(sender.downgrade()),
+            EventSender::Unbounded(_) | EventSender::Broadcast(_) => None,
+        }
+    }
+}
+
+impl<T> Clone for EventSender<T> {
+    fn clone(&self) -> Self {
+        match self {
+            EventSender::Unbounded(sender) => EventSender::Unbounded(sender.clone()),
+            EventSender::Bounded(sender) => EventSender::Bounded(sender.clone()),
+            EventSender::Broadcast(sender) => EventSender::Broadcast(sender.clone()),
+        }
+    }
+}
+
+impl<T> From<UnboundedSender<T>> for EventSender<T> {
+    fn from(sender: UnboundedSender<T>) -> Self {
+        EventSender::Unbounded(sender)
+    }
+}
+
+impl<T> From<Sender<T>> for EventSender<T> {
+    fn from(sender: Sender<T>) -> Self {
+        EventSender::Bounded(sender)
+    }
+}
+
+impl<T> From<broadcast::Sender<Arc<T>>> for EventSender<T> {
+    fn from(sender: broadcast::Sender<Arc<T>>) -> Self {
+        EventSender::Broadcast(sender)
+    }
+}
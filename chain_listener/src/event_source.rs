@@ -0,0 +1,100 @@
+pub mod grpc;
+pub mod memory;
+pub mod record;
+pub mod rest;
+
+use aptos_protos::transaction::v1::{move_type::Content, Event};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+
+pub use grpc::GrpcEventSource;
+pub use memory::{MemoryEventFeed, MemoryEventSource};
+pub use record::{RecordingEventSource, ReplayEventSource};
+pub use rest::RestEventSource;
+
+pub type SourceError = Box<dyn std::error::Error + Send + Sync>;
+
+/// An on-chain event, normalized so that it no longer depends on the transport it came from.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RawEvent {
+    pub version: u64,
+    /// Timestamp of the transaction that emitted the event, in microseconds
+    pub timestamp_us: u64,
+    pub address: String,
+    pub module: String,
+    pub name: String,
+    /// JSON encoded event fields, as emitted by the contract
+    pub data: String,
+}
+
+impl RawEvent {
+    /// Converts an event from the transaction stream, skipping non-struct event types.
+    pub fn from_proto(version: u64, timestamp_us: u64, event: &Event) -> Option<Self> {
+        match event.r#type.as_ref()?.content.as_ref()? {
+            Content::Struct(s) => Some(RawEvent {
+                version,
+                timestamp_us,
+                address: s.address.to_owned(),
+                module: s.module.to_owned(),
+                name: s.name.to_owned(),
+                data: event.data.to_owned(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// Parses a fully qualified type such as `0x1::module::Name<T>`.
+    pub fn from_type_str(
+        version: u64,
+        timestamp_us: u64,
+        type_str: &str,
+        data: String,
+    ) -> Option<Self> {
+        let mut parts = type_str.splitn(3, "::");
+        let address = parts.next()?;
+        let module = parts.next()?;
+        let name = parts.next()?;
+        let name = match name.find('<') {
+            Some(idx) => &name[..idx],
+            None => name,
+        };
+
+        Some(RawEvent {
+            version,
+            timestamp_us,
+            address: address.to_owned(),
+            module: module.to_owned(),
+            name: name.to_owned(),
+            data,
+        })
+    }
+}
+
+/// Events found in a range of transactions.
+#[derive(Debug, Default)]
+pub struct EventBatch {
+    /// Highest transaction version scanned to produce this batch
+    pub latest_version: u64,
+    /// Latest version known to the chain, if the source can tell
+    pub chain_tip: Option<u64>,
+    /// Number of transactions scanned to produce this batch
+    pub transactions: u64,
+    /// Number of times the source reconnected since the previous batch
+    pub reconnects: u64,
+    pub events: Vec<RawEvent>,
+}
+
+/// A source of on-chain events for the chain listener.
+#[async_trait]
+pub trait EventSource: Send {
+    /// Waits for the next batch of events.
+    /// Returns `Ok(None)` once the source is exhausted and will never produce more events.
+    async fn next_batch(&mut self) -> Result<Option<EventBatch>, SourceError>;
+}
+
+#[async_trait]
+impl<S: EventSource + ?Sized> EventSource for Box<S> {
+    async fn next_batch(&mut self) -> Result<Option<EventBatch>, SourceError> {
+        (**self).next_batch().await
+    }
+}
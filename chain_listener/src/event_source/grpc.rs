@@ -0,0 +1,260 @@
+use std::str::FromStr;
+use std::time::Duration;
+
+use aptos_protos::indexer::v1::raw_data_client::RawDataClient;
+use aptos_protos::indexer::v1::{GetTransactionsRequest, TransactionsResponse};
+use aptos_protos::transaction::v1::transaction::TxnData;
+use async_trait::async_trait;
+use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
+use tokio_stream::StreamExt;
+use tonic::metadata::MetadataValue;
+use tonic::service::Interceptor;
+use tonic::transport::{Certificate, Channel, ClientTlsConfig};
+use tokio::time::Instant;
+use tonic::{Request, Streaming};
+
+use crate::config::ListenerConfig;
+
+use super::rest::fetch_ledger_version;
+use super::{EventBatch, EventSource, RawEvent, SourceError};
+
+const CHAIN_TIP_REFRESH: Duration = Duration::from_secs(5);
+
+#[derive(Clone)]
+struct AuthInterceptor {
+    pub token: String,
+}
+
+impl Interceptor for AuthInterceptor {
+    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, tonic::Status> {
+        // Add the authorization header with the token
+        let token = format!("Bearer {}", self.token);
+        let metadata_value = MetadataValue::from_str(&token)
+            .map_err(|_| tonic::Status::unauthenticated("Invalid token"))?;
+        request
+            .metadata_mut()
+            .insert("authorization", metadata_value);
+
+        Ok(request)
+    }
+}
+
+/// Reads events from the Aptos Transaction Stream Service. Requires an indexer auth token.
+pub struct GrpcEventSource {
+    api_key: String,
+    indexer_url: String,
+    config: ListenerConfig,
+    // version asked by `starting_from`, the chain tip when unset
+    starting_version: Option<u64>,
+    // keep track of latest version in case the stream stops
+    latest_version: Option<u64>,
+    stream: Option<Streaming<TransactionsResponse>>,
+    // reconnections not yet reported in a batch
+    reconnects: u64,
+    // the stream does not tell the chain tip, it is polled from a fullnode instead
+    tip_node_url: Option<String>,
+    tip_client: reqwest::Client,
+    chain_tip: Option<u64>,
+    tip_refreshed_at: Option<Instant>,
+}
+
+impl GrpcEventSource {
+    pub fn new(api_key: &str, indexer_url: &str) -> Self {
+        GrpcEventSource {
+            api_key: api_key.to_owned(),
+            indexer_url: indexer_url.to_owned(),
+            config: ListenerConfig::default(),
+            starting_version: None,
+            latest_version: None,
+            stream: None,
+            reconnects: 0,
+            tip_node_url: None,
+            tip_client: reqwest::Client::new(),
+            chain_tip: None,
+            tip_refreshed_at: None,
+        }
+    }
+
+    /// Poll the given fullnode for the chain tip, so that the listener can report its lag.
+    pub fn with_chain_tip_from(mut self, node_url: &str) -> Self {
+        self.tip_node_url = Some(node_url.to_owned());
+        self
+    }
+
+    /// Apply the given transport settings to the connection.
+    pub fn with_config(mut self, config: ListenerConfig) -> Self {
+        self.config = config;
+        self
+    }
+
+    /// Start streaming from the given version instead of the chain tip.
+    pub fn starting_from(mut self, version: u64) -> Self {
+        self.starting_version = Some(version);
+        self
+    }
+
+    // resumes after the last received transaction, `None` streams from the chain tip
+    fn next_version(&self) -> Option<u64> {
+        match self.latest_version {
+            Some(version) => Some(version + 1),
+            None => self.starting_version,
+        }
+    }
+
+    // does not borrow `self`, the stream is not `Sync` and the future must stay `Send`
+    async fn connect(
+        api_key: String,
+        indexer_url: String,
+        config: ListenerConfig,
+        starting_version: Option<u64>,
+    ) -> Result<Streaming<TransactionsResponse>, SourceError> {
+        let interceptor = AuthInterceptor { token: api_key };
+
+        // Create a gRPC channel
+        let mut endpoint = Channel::from_shared(indexer_url)?;
+        if let Some(timeout) = config.connect_timeout {
+            endpoint = endpoint.connect_timeout(timeout);
+        }
+        if let Some(interval) = config.keepalive_interval {
+            endpoint = endpoint
+                .http2_keep_alive_interval(interval)
+                .keep_alive_while_idle(true);
+        }
+        if let Some(timeout) = config.keepalive_timeout {
+            endpoint = endpoint.keep_alive_timeout(timeout);
+        }
+        if let Some(pem) = config.ca_certificates {
+            // the system roots stay trusted, the given certificates are added to them
+            endpoint = endpoint
+                .tls_config(ClientTlsConfig::new().ca_certificate(Certificate::from_pem(pem)))?;
+        }
+        let channel = endpoint.connect().await?;
+
+        let mut client = RawDataClient::with_interceptor(channel, interceptor);
+        if let Some(compression) = config.compression {
+            client = client.accept_compressed(compression.encoding());
+        }
+        if let Some(size) = config.max_decoding_message_size {
+            client = client.max_decoding_message_size(size);
+        }
+
+        let req = GetTransactionsRequest {
+            starting_version,
+            transactions_count: None,
+            batch_size: config.batch_size,
+        };
+        let response = client.get_transactions(req).await?;
+
+        Ok(response.into_inner())
+    }
+}
+
+#[async_trait]
+impl EventSource for GrpcEventSource {
+    async fn next_batch(&mut self) -> Result<Option<EventBatch>, SourceError> {
+        loop {
+            if self.stream.is_none() {
+                let stream = Self::connect(
+                    self.api_key.to_owned(),
+                    self.indexer_url.to_owned(),
+                    self.config.clone(),
+                    self.next_version(),
+                )
+                .await;
+                match stream {
+                    Ok(stream) => self.stream = Some(stream),
+                    Err(e) => {
+                        // reported with the next batch, once connected
+                        self.reconnects += 1;
+                        return Err(e);
+                    }
+                }
+            }
+
+            let received = match self.stream.as_mut().unwrap().next().await {
+                Some(Ok(received)) => received,
+                Some(Err(status)) => {
+                    self.stream = None;
+                    self.reconnects += 1;
+                    return Err(status.into());
+                }
+                None => {
+                    println!(
+                        "Chain listener has stopped: restarting from version {:?}",
+                        self.latest_version
+                    );
+                    self.stream = None;
+                    self.reconnects += 1;
+                    continue;
+                }
+            };
+
+            // update lastest received version
+            if let Some(version) = received.transactions.iter().map(|tx| tx.version).max() {
+                self.latest_version = Some(version);
+            }
+
+            let events: Vec<Vec<RawEvent>> = received
+                .transactions
+                .par_iter()
+                .filter_map(|txn| match &txn.txn_data {
+                    Some(TxnData::User(data)) => {
+                        let timestamp_us = txn
+                            .timestamp
+                            .as_ref()
+                            .map(|t| t.seconds as u64 * 1_000_000 + t.nanos as u64 / 1_000)
+                            .unwrap_or_default();
+                        Some(
+                            data.events
+                                .iter()
+                                .filter_map(|e| RawEvent::from_proto(txn.version, timestamp_us, e))
+                                .collect(),
+                        )
+                    }
+                    _ => None,
+                })
+                .collect();
+
+            if let Some(node_url) = self.tip_node_url.to_owned() {
+                let refresh_due = match self.tip_refreshed_at {
+                    Some(at) => at.elapsed() >= CHAIN_TIP_REFRESH,
+                    None => true,
+                };
+                if refresh_due {
+                    self.tip_refreshed_at = Some(Instant::now());
+                    let client = self.tip_client.clone();
+                    // the chain tip is informative only, keep streaming if the fullnode fails
+                    if let Ok(version) = fetch_ledger_version(&client, &node_url).await {
+                        self.chain_tip = Some(version);
+                    }
+                }
+            }
+
+            return Ok(Some(EventBatch {
+                latest_version: self.latest_version.unwrap_or_default(),
+                chain_tip: self.chain_tip,
+                transactions: received.transactions.len() as u64,
+                reconnects: std::mem::take(&mut self.reconnects),
+                events: events.into_iter().flatten().collect(),
+            }));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn starts_from_the_given_version() {
+        let source = GrpcEventSource::new("key", "https://indexer.example.com");
+        assert_eq!(source.next_version(), None);
+
+        let mut source = source.starting_from(0);
+        assert_eq!(source.next_version(), Some(0));
+
+        // once transactions were received, a reconnection resumes after them
+        source.latest_version = Some(41);
+        assert_eq!(source.next_version(), Some(42));
+    }
+}
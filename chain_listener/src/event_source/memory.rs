@@ -0,0 +1,271 @@
+use async_trait::async_trait;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+
+use super::{EventBatch, EventSource, RawEvent, SourceError};
+
+/// Event source fed by hand, meant for running the orchestrator and worker logic offline.
+/// The source is exhausted once every `MemoryEventFeed` has been dropped.
+pub struct MemoryEventSource {
+    receiver: UnboundedReceiver<Vec<RawEvent>>,
+}
+
+#[derive(Clone)]
+pub struct MemoryEventFeed {
+    sender: UnboundedSender<Vec<RawEvent>>,
+}
+
+impl MemoryEventSource {
+    pub fn new() -> (MemoryEventFeed, MemoryEventSource) {
+        let (sender, receiver) = mpsc::unbounded_channel();
+        (MemoryEventFeed { sender }, MemoryEventSource { receiver })
+    }
+}
+
+impl MemoryEventFeed {
+    /// Pushes the events as a single batch. Returns false if the source was dropped.
+    pub fn push(&self, events: Vec<RawEvent>) -> bool {
+        self.sender.send(events).is_ok()
+    }
+}
+
+#[async_trait]
+impl EventSource for MemoryEventSource {
+    async fn next_batch(&mut self) -> Result<Option<EventBatch>, SourceError> {
+        Ok(self.receiver.recv().await.map(|events| {
+            let mut versions: Vec<u64> = events.iter().map(|e| e.version).collect();
+            versions.sort_unstable();
+            versions.dedup();
+            EventBatch {
+                latest_version: versions.last().copied().unwrap_or_default(),
+                chain_tip: None,
+                transactions: versions.len() as u64,
+                reconnects: 0,
+                events,
+            }
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proxirun_sdk::constants::CONTRACT_MODULE;
+    use proxirun_sdk::events::ContractEvent;
+    use tokio::sync::mpsc;
+
+    use super::*;
+    use crate::event_source::{RecordingEventSource, ReplayEventSource};
+    use crate::events::DecodeError;
+    use crate::events_listener::run_listener_with_source;
+    use crate::stats::ListenerStats;
+
+    const OTHER_ADDRESS: &str = "0x42";
+
+    fn raw_event(version: u64, address: &str, name: &str, data: &str) -> RawEvent {
+        RawEvent {
+            version,
+            timestamp_us: version * 1_000_000,
+            address: address.to_owned(),
+            module: CONTRACT_MODULE.name.to_string(),
+            name: name.to_owned(),
+            data: data.to_owned(),
+        }
+    }
+
+    fn contract_event(version: u64, name: &str, data: &str) -> RawEvent {
+        raw_event(
+            version,
+            &CONTRACT_MODULE.address.to_hex_literal(),
+            name,
+            data,
+        )
+    }
+
+    /// Runs the listener over the given batches until the feed is exhausted.
+    async fn listen(
+        batches: Vec<Vec<RawEvent>>,
+    ) -> (Vec<ContractEvent>, Vec<DecodeError>, ListenerStats) {
+        let (feed, source) = MemoryEventSource::new();
+        for batch in batches {
+            assert!(feed.push(batch));
+        }
+        drop(feed);
+
+        listen_to(source).await
+    }
+
+    /// Runs the listener until the source is exhausted.
+    async fn listen_to(
+        source: impl EventSource + 'static,
+    ) -> (Vec<ContractEvent>, Vec<DecodeError>, ListenerStats) {
+        let (sender_events, mut receiver_events) = mpsc::unbounded_channel();
+        let (sender_decode_errors, mut receiver_decode_errors) = mpsc::unbounded_channel();
+        let stats = run_listener_with_source(
+            source,
+            CONTRACT_MODULE.to_owned(),
+            sender_events,
+            sender_decode_errors,
+        )
+        .await
+        .unwrap();
+
+        // the listener drops its senders once the source is exhausted
+        let mut events = vec![];
+        while let Some(event) = receiver_events.recv().await {
+            events.push(event);
+        }
+        let mut decode_errors = vec![];
+        while let Some(e) = receiver_decode_errors.recv().await {
+            decode_errors.push(e);
+        }
+
+        (events, decode_errors, stats)
+    }
+
+    #[tokio::test]
+    async fn decodes_contract_events() {
+        let (events, decode_errors, _) = listen(vec![vec![
+            contract_event(
+                1,
+                "OnNewWorkRequestBid",
+                r#"{"request_id":"7","bidder":"0xb1d","price":"100"}"#,
+            ),
+            contract_event(
+                2,
+                "OnBidWon",
+                r#"{"request_id":"7","winner":"0xb1d","bid_price":"100"}"#,
+            ),
+            contract_event(3, "OnFutureEvent", r#"{"request_id":"7"}"#),
+        ]])
+        .await;
+
+        assert!(decode_errors.is_empty());
+        assert_eq!(events.len(), 3);
+        match &events[0] {
+            ContractEvent::OnNewWorkRequestBid(bid) => {
+                assert_eq!(bid.request_id, 7);
+                assert_eq!(bid.bidder, "0xb1d");
+                assert_eq!(bid.price, 100);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        match &events[1] {
+            ContractEvent::OnBidWon(won) => {
+                assert_eq!(won.request_id, 7);
+                assert_eq!(won.winner, "0xb1d");
+                assert_eq!(won.bid_price, 100);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        match &events[2] {
+            ContractEvent::Unknown(unknown) => assert_eq!(unknown.name, "OnFutureEvent"),
+            event => panic!("unexpected event {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn filters_other_contracts_and_reports_decode_errors() {
+        let (events, decode_errors, _) = listen(vec![vec![
+            raw_event(
+                1,
+                OTHER_ADDRESS,
+                "OnAuctionFailure",
+                r#"{"request_id":"1"}"#,
+            ),
+            contract_event(2, "OnAuctionFailure", r#"{"request_id":"not a number"}"#),
+            contract_event(3, "OnAuctionFailure", r#"{"request_id":"3"}"#),
+        ]])
+        .await;
+
+        assert_eq!(events.len(), 1);
+        match &events[0] {
+            ContractEvent::OnAuctionFailure(failure) => assert_eq!(failure.request_id, 3),
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(decode_errors.len(), 1);
+        assert_eq!(decode_errors[0].event.version, 2);
+    }
+
+    #[tokio::test]
+    async fn counts_processed_events() {
+        let (_, _, stats) = listen(vec![
+            vec![
+                contract_event(1, "OnWorkRequestCompleted", r#"{"request_id":"1"}"#),
+                contract_event(1, "OnWorkRequestCompleted", r#"{"request_id":"2"}"#),
+                raw_event(
+                    2,
+                    OTHER_ADDRESS,
+                    "OnWorkRequestCompleted",
+                    r#"{"request_id":"3"}"#,
+                ),
+            ],
+            vec![contract_event(5, "OnBidWon", "{}")],
+        ])
+        .await;
+
+        assert_eq!(stats.latest_version(), 5);
+        assert_eq!(stats.transactions_scanned(), 3);
+        assert_eq!(stats.decode_failures(), 1);
+        assert_eq!(stats.queue_depth(), None);
+        let matched = stats.events_matched();
+        assert_eq!(matched[1], ("OnWorkRequestCompleted", 2));
+        assert_eq!(matched.iter().map(|(_, count)| count).sum::<u64>(), 2);
+    }
+
+    #[tokio::test]
+    async fn replays_recorded_events() {
+        let path = std::env::temp_dir()
+            .join(format!("proxirun-replay-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let (feed, source) = MemoryEventSource::new();
+        let mut recording = RecordingEventSource::new(source, CONTRACT_MODULE.to_owned(), &path)
+            .await
+            .unwrap();
+        assert!(feed.push(vec![
+            contract_event(
+                1,
+                "OnBidWon",
+                r#"{"request_id":"7","winner":"0xb1d","bid_price":"100"}"#,
+            ),
+            raw_event(1, OTHER_ADDRESS, "OnAuctionFailure", r#"{"request_id":"8"}"#),
+            contract_event(1, "OnAuctionFailure", r#"{"request_id":"not a number"}"#),
+            contract_event(1, "OnAuctionFailure", r#"{"request_id":"9"}"#),
+        ]));
+        assert!(feed.push(vec![contract_event(
+            4,
+            "OnWorkRequestCompleted",
+            r#"{"request_id":"7"}"#,
+        )]));
+        drop(feed);
+        while recording.next_batch().await.unwrap().is_some() {}
+
+        for speed in [0.0, -1.0, f64::NAN, f64::NEG_INFINITY] {
+            assert!(ReplayEventSource::open(&path, speed).await.is_err());
+        }
+        let replay = ReplayEventSource::open(&path, f64::INFINITY).await.unwrap();
+        let (events, decode_errors, stats) = listen_to(replay).await;
+        std::fs::remove_file(&path).unwrap();
+
+        // only the decoded events of the watched contract are recorded
+        assert!(decode_errors.is_empty());
+        assert_eq!(events.len(), 3);
+        match &events[0] {
+            ContractEvent::OnBidWon(won) => {
+                assert_eq!(won.request_id, 7);
+                assert_eq!(won.winner, "0xb1d");
+                assert_eq!(won.bid_price, 100);
+            }
+            event => panic!("unexpected event {:?}", event),
+        }
+        match &events[1] {
+            ContractEvent::OnAuctionFailure(failure) => assert_eq!(failure.request_id, 9),
+            event => panic!("unexpected event {:?}", event),
+        }
+        match &events[2] {
+            ContractEvent::OnWorkRequestCompleted(completed) => assert_eq!(completed.request_id, 7),
+            event => panic!("unexpected event {:?}", event),
+        }
+        assert_eq!(stats.latest_version(), 4);
+        assert_eq!(stats.transactions_scanned(), 2);
+    }
+}
@@ -0,0 +1,145 @@
+use std::path::Path;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use proxirun_sdk::events::ContractEvent;
+use tokio::fs::{File, OpenOptions};
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader, BufWriter, Lines};
+use tokio::time::sleep;
+
+use super::{EventBatch, EventSource, RawEvent, SourceError};
+use crate::events::{ContractEventExtractor, EventEnvelope};
+use crate::filter::EventFilter;
+
+/// Passes the events of another source through, while appending the decoded events
+/// of the watched contracts to a JSONL file, one `EventEnvelope` per line.
+pub struct RecordingEventSource<S> {
+    inner: S,
+    filter: EventFilter,
+    writer: BufWriter<File>,
+}
+
+impl<S: EventSource> RecordingEventSource<S> {
+    pub async fn new(
+        inner: S,
+        contracts: impl Into<EventFilter>,
+        path: impl AsRef<Path>,
+    ) -> Result<Self, SourceError> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .await?;
+
+        Ok(RecordingEventSource {
+            inner,
+            filter: contracts.into(),
+            writer: BufWriter::new(file),
+        })
+    }
+}
+
+#[async_trait]
+impl<S: EventSource> EventSource for RecordingEventSource<S> {
+    async fn next_batch(&mut self) -> Result<Option<EventBatch>, SourceError> {
+        let batch = match self.inner.next_batch().await? {
+            Some(batch) => batch,
+            None => return Ok(None),
+        };
+
+        for raw in &batch.events {
+            // events that fail to decode are left to the dead-letter channel of the listener
+            if let Some(Ok(event)) = ContractEvent::extract_event_data_with_filters(raw, &self.filter)
+            {
+                let mut line = serde_json::to_string(&EventEnvelope::new(raw, event))?;
+                line.push('\n');
+                self.writer.write_all(line.as_bytes()).await?;
+            }
+        }
+        self.writer.flush().await?;
+
+        Ok(Some(batch))
+    }
+}
+
+/// Replays a file written by `RecordingEventSource`.
+///
+/// `speed` scales the original delay between transactions: 1.0 replays in real time,
+/// 10.0 ten times faster and `f64::INFINITY` without waiting at all. Any other speed that is
+/// not a positive number is rejected.
+pub struct ReplayEventSource {
+    lines: Lines<BufReader<File>>,
+    speed: f64,
+    // first envelope of the next batch, read ahead while grouping by version
+    pending: Option<EventEnvelope>,
+    last_timestamp_us: Option<u64>,
+}
+
+impl ReplayEventSource {
+    pub async fn open(path: impl AsRef<Path>, speed: f64) -> Result<Self, SourceError> {
+        if speed.is_nan() || speed <= 0.0 {
+            return Err(format!("Invalid replay speed: {}", speed).into());
+        }
+        let file = File::open(path).await?;
+
+        Ok(ReplayEventSource {
+            lines: BufReader::new(file).lines(),
+            speed,
+            pending: None,
+            last_timestamp_us: None,
+        })
+    }
+
+    async fn next_envelope(&mut self) -> Result<Option<EventEnvelope>, SourceError> {
+        if let Some(envelope) = self.pending.take() {
+            return Ok(Some(envelope));
+        }
+
+        while let Some(line) = self.lines.next_line().await? {
+            if line.trim().is_empty() {
+                continue;
+            }
+            return Ok(Some(serde_json::from_str(&line)?));
+        }
+
+        Ok(None)
+    }
+}
+
+#[async_trait]
+impl EventSource for ReplayEventSource {
+    async fn next_batch(&mut self) -> Result<Option<EventBatch>, SourceError> {
+        let first = match self.next_envelope().await? {
+            Some(envelope) => envelope,
+            None => return Ok(None),
+        };
+
+        // keep the original pace between transactions
+        if let Some(last_timestamp_us) = self.last_timestamp_us {
+            let delay_us = first.timestamp_us.saturating_sub(last_timestamp_us) as f64 / self.speed;
+            if delay_us >= 1.0 {
+                sleep(Duration::from_micros(delay_us as u64)).await;
+            }
+        }
+        self.last_timestamp_us = Some(first.timestamp_us);
+
+        // events of a same transaction are replayed together
+        let version = first.version;
+        let mut events: Vec<RawEvent> = vec![first.to_raw_event()];
+        while let Some(envelope) = self.next_envelope().await? {
+            if envelope.version != version {
+                self.pending = Some(envelope);
+                break;
+            }
+            events.push(envelope.to_raw_event());
+        }
+
+        Ok(Some(EventBatch {
+            latest_version: version,
+            chain_tip: None,
+            transactions: 1,
+            reconnects: 0,
+            events,
+        }))
+    }
+}
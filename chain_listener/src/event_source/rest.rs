@@ -0,0 +1,169 @@
+use std::time::Duration;
+
+use async_trait::async_trait;
+use serde_json::Value;
+use tokio::time::sleep;
+
+use super::{EventBatch, EventSource, RawEvent, SourceError};
+
+const DEFAULT_POLL_INTERVAL: Duration = Duration::from_secs(1);
+const DEFAULT_PAGE_SIZE: u16 = 100;
+const LEDGER_VERSION_HEADER: &str = "x-aptos-ledger-version";
+
+/// Polls the transactions of a fullnode REST API for module events.
+/// Slower than the transaction stream, but needs no indexer auth token.
+pub struct RestEventSource {
+    client: reqwest::Client,
+    node_url: String,
+    next_version: Option<u64>,
+    // latest ledger version seen, the fullnode rejects the pages that start past it
+    chain_tip: Option<u64>,
+    poll_interval: Duration,
+    page_size: u16,
+}
+
+impl RestEventSource {
+    pub fn new(node_url: &str) -> Self {
+        RestEventSource {
+            client: reqwest::Client::new(),
+            node_url: node_url.trim_end_matches('/').to_owned(),
+            next_version: None,
+            chain_tip: None,
+            poll_interval: DEFAULT_POLL_INTERVAL,
+            page_size: DEFAULT_PAGE_SIZE,
+        }
+    }
+
+    /// Start polling from the given version instead of the chain tip.
+    pub fn starting_from(mut self, version: u64) -> Self {
+        self.next_version = Some(version);
+        self
+    }
+
+    pub fn poll_interval(mut self, poll_interval: Duration) -> Self {
+        self.poll_interval = poll_interval;
+        self
+    }
+
+    pub fn page_size(mut self, page_size: u16) -> Self {
+        self.page_size = page_size;
+        self
+    }
+
+    async fn ledger_version(&self) -> Result<u64, SourceError> {
+        fetch_ledger_version(&self.client, &self.node_url).await
+    }
+
+    /// Returns a page of transactions along with the current ledger version.
+    async fn transactions(&self, start: u64) -> Result<(Vec<Value>, Option<u64>), SourceError> {
+        let response = self
+            .client
+            .get(format!("{}/v1/transactions", self.node_url))
+            .query(&[("start", start), ("limit", self.page_size as u64)])
+            .send()
+            .await?
+            .error_for_status()?;
+
+        let ledger_version = response
+            .headers()
+            .get(LEDGER_VERSION_HEADER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok());
+
+        Ok((response.json().await?, ledger_version))
+    }
+}
+
+/// Fetches the latest version known to the fullnode.
+pub(crate) async fn fetch_ledger_version(
+    client: &reqwest::Client,
+    node_url: &str,
+) -> Result<u64, SourceError> {
+    let info: Value = client
+        .get(format!("{}/v1", node_url.trim_end_matches('/')))
+        .send()
+        .await?
+        .error_for_status()?
+        .json()
+        .await?;
+
+    parse_u64(&info["ledger_version"]).ok_or_else(|| "Missing ledger version".into())
+}
+
+#[async_trait]
+impl EventSource for RestEventSource {
+    async fn next_batch(&mut self) -> Result<Option<EventBatch>, SourceError> {
+        let start = match self.next_version {
+            Some(version) => version,
+            None => {
+                let version = self.ledger_version().await?;
+                self.next_version = Some(version);
+                version
+            }
+        };
+
+        loop {
+            // caught up, wait for the ledger to move instead of asking for a page it answers with 400
+            let caught_up = match self.chain_tip {
+                Some(chain_tip) => start > chain_tip,
+                None => true,
+            };
+            if caught_up {
+                let chain_tip = self.ledger_version().await?;
+                self.chain_tip = Some(chain_tip);
+                if start > chain_tip {
+                    sleep(self.poll_interval).await;
+                    continue;
+                }
+            }
+
+            let (transactions, chain_tip) = self.transactions(start).await?;
+            if chain_tip.is_some() {
+                self.chain_tip = chain_tip;
+            }
+            if transactions.is_empty() {
+                sleep(self.poll_interval).await;
+                continue;
+            }
+
+            let mut batch = EventBatch {
+                chain_tip,
+                transactions: transactions.len() as u64,
+                ..Default::default()
+            };
+            for txn in &transactions {
+                let version = match parse_u64(&txn["version"]) {
+                    Some(version) => version,
+                    None => continue,
+                };
+                batch.latest_version = batch.latest_version.max(version);
+
+                if txn["type"] != "user_transaction" {
+                    continue;
+                }
+
+                let timestamp_us = parse_u64(&txn["timestamp"]).unwrap_or_default();
+                if let Some(events) = txn["events"].as_array() {
+                    for e in events {
+                        if let Some(type_str) = e["type"].as_str() {
+                            let data = e["data"].to_string();
+                            if let Some(raw) =
+                                RawEvent::from_type_str(version, timestamp_us, type_str, data)
+                            {
+                                batch.events.push(raw);
+                            }
+                        }
+                    }
+                }
+            }
+
+            self.next_version = Some(batch.latest_version + 1);
+            return Ok(Some(batch));
+        }
+    }
+}
+
+// the REST API encodes u64 values as strings
+fn parse_u64(value: &Value) -> Option<u64> {
+    value.as_str()?.parse().ok()
+}
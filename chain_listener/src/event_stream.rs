@@ -0,0 +1,112 @@
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use proxirun_sdk::events::ContractEvent;
+use tokio_stream::Stream;
+
+use crate::event_source::{EventBatch, EventSource, SourceError};
+use crate::events::{ContractEventExtractor, DecodeError, EventEnvelope};
+use crate::filter::EventFilter;
+
+#[derive(Debug)]
+pub enum ListenerError {
+    /// The event source failed, polling the stream again retries it
+    Source(SourceError),
+    Decode(DecodeError),
+}
+
+impl fmt::Display for ListenerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenerError::Source(e) => write!(f, "Event source error: {}", e),
+            ListenerError::Decode(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+impl std::error::Error for ListenerError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            ListenerError::Source(e) => Some(e.as_ref()),
+            ListenerError::Decode(e) => Some(e),
+        }
+    }
+}
+
+type BoxedSource = Box<dyn EventSource>;
+type NextBatch =
+    Pin<Box<dyn Future<Output = (BoxedSource, Result<Option<EventBatch>, SourceError>)> + Send>>;
+
+enum State {
+    Idle(BoxedSource),
+    Polling(NextBatch),
+    Done,
+}
+
+/// The events of the watched contracts as a `Stream`, for callers that want to drive
+/// the listener from their own task instead of having `run_listener` spawn one.
+pub struct EventStream {
+    state: State,
+    filter: EventFilter,
+    pending: VecDeque<Result<EventEnvelope, ListenerError>>,
+}
+
+impl EventStream {
+    pub fn new<S: EventSource + 'static>(source: S, contracts: impl Into<EventFilter>) -> Self {
+        EventStream {
+            state: State::Idle(Box::new(source)),
+            filter: contracts.into(),
+            pending: VecDeque::new(),
+        }
+    }
+
+    fn push_batch(&mut self, batch: EventBatch) {
+        for raw in &batch.events {
+            match ContractEvent::extract_event_data_with_filters(raw, &self.filter) {
+                Some(Ok(event)) => self.pending.push_back(Ok(EventEnvelope::new(raw, event))),
+                Some(Err(e)) => self.pending.push_back(Err(ListenerError::Decode(e))),
+                None => (),
+            }
+        }
+    }
+}
+
+impl Stream for EventStream {
+    type Item = Result<EventEnvelope, ListenerError>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        loop {
+            if let Some(item) = self.pending.pop_front() {
+                return Poll::Ready(Some(item));
+            }
+
+            match std::mem::replace(&mut self.state, State::Done) {
+                State::Idle(mut source) => {
+                    self.state = State::Polling(Box::pin(async move {
+                        let batch = source.next_batch().await;
+                        (source, batch)
+                    }));
+                }
+                State::Polling(mut next_batch) => match next_batch.as_mut().poll(cx) {
+                    Poll::Pending => {
+                        self.state = State::Polling(next_batch);
+                        return Poll::Pending;
+                    }
+                    Poll::Ready((source, Ok(Some(batch)))) => {
+                        self.state = State::Idle(source);
+                        self.push_batch(batch);
+                    }
+                    Poll::Ready((_, Ok(None))) => return Poll::Ready(None),
+                    Poll::Ready((source, Err(e))) => {
+                        self.state = State::Idle(source);
+                        return Poll::Ready(Some(Err(ListenerError::Source(e))));
+                    }
+                },
+                State::Done => return Poll::Ready(None),
+            }
+        }
+    }
+}
@@ -1,91 +1,124 @@
-use aptos_protos::transaction::v1::{move_type::Content, Event};
+use std::fmt;
+
 use proxirun_sdk::events::*;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 
-pub trait ContractEventExtractor where Self: Sized {
-    fn extract_event_data_with_filters(
-        event: &Event,
-        contract_address: &str,
-        module_name: &str,
-    ) -> Option<Self>;
+use crate::event_source::RawEvent;
+use crate::filter::EventFilter;
 
-    fn extract_event_data(event: Event) -> Option<Self>;
+/// A contract event that could not be decoded, kept with the raw event for inspection.
+#[derive(Debug, Clone, Serialize)]
+pub struct DecodeError {
+    pub event: RawEvent,
+    pub error: String,
 }
 
-impl ContractEventExtractor for ContractEvent {
-    fn extract_event_data_with_filters(
-        event: &Event,
-        contract_address: &str,
-        module_name: &str,
-    ) -> Option<Self> {
-        if let Some(event_type) = &event.r#type {
-            if let Some(event_content) = &event_type.content {
-                match event_content {
-                    Content::Struct(s) => {
-                        if s.module != module_name && &s.address != contract_address {
-                            return None;
-                        }
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "Failed to decode {}::{}::{} at version {}: {}",
+            self.event.address, self.event.module, self.event.name, self.event.version, self.error
+        )
+    }
+}
 
-                        return match s.name.as_str() {
-                            "OnNewWorkRequest" => Some(ContractEvent::OnNewWorkRequest(
-                                serde_json::from_str(&event.data).unwrap(),
-                            )),
-                            "OnWorkRequestCompleted" => {
-                                Some(ContractEvent::OnWorkRequestCompleted(
-                                    serde_json::from_str(&event.data).unwrap(),
-                                ))
-                            }
-                            "OnNewWorkRequestBid" => Some(ContractEvent::OnNewWorkRequestBid(
-                                serde_json::from_str(&event.data).unwrap(),
-                            )),
-                            "OnBidWon" => Some(ContractEvent::OnBidWon(
-                                serde_json::from_str(&event.data).unwrap(),
-                            )),
-                            "OnAuctionFailure" => Some(ContractEvent::OnAuctionFailure(
-                                serde_json::from_str(&event.data).unwrap(),
-                            )),
-                            _ => None, //panic!("Unexpected event tag"),
-                        };
-                    }
-                    _ => return None, // panic!("Unexpected"),
-                }
-            } else {
-                return None;
-            }
-        } else {
-            return None;
+impl std::error::Error for DecodeError {}
+
+/// A decoded contract event along with where and when it was emitted.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    pub version: u64,
+    pub timestamp_us: u64,
+    pub address: String,
+    pub module: String,
+    pub event: ContractEvent,
+}
+
+impl EventEnvelope {
+    pub fn new(raw: &RawEvent, event: ContractEvent) -> Self {
+        EventEnvelope {
+            version: raw.version,
+            timestamp_us: raw.timestamp_us,
+            address: raw.address.to_owned(),
+            module: raw.module.to_owned(),
+            event,
         }
     }
 
-    fn extract_event_data(event: Event) -> Option<Self> {
-        if let Some(event_type) = event.r#type {
-            match event_type.content.unwrap() {
-                Content::Struct(s) => {
-                    return match s.name.as_str() {
-                        "OnNewWorkRequest" => Some(ContractEvent::OnNewWorkRequest(
-                            serde_json::from_str(&event.data).unwrap(),
-                        )),
-                        "OnWorkRequestCompleted" => Some(ContractEvent::OnWorkRequestCompleted(
-                            serde_json::from_str(&event.data).unwrap(),
-                        )),
-                        "OnNewWorkRequestBid" => Some(ContractEvent::OnNewWorkRequestBid(
-                            serde_json::from_str(&event.data).unwrap(),
-                        )),
-                        "OnBidWon" => Some(ContractEvent::OnBidWon(
-                            serde_json::from_str(&event.data).unwrap(),
-                        )),
-                        "OnAuctionFailure" => Some(ContractEvent::OnAuctionFailure(
-                            serde_json::from_str(&event.data).unwrap(),
-                        )),
-                        _ => panic!("Unexpected event tag"),
-                    }
-                }
-                _ => panic!("Unexpected"),
+    /// Encodes the event back into the form emitted by the contract.
+    pub fn to_raw_event(&self) -> RawEvent {
+        let (name, data) = match &self.event {
+            ContractEvent::OnNewWorkRequest(e) => ("OnNewWorkRequest", serde_json::to_string(e)),
+            ContractEvent::OnWorkRequestCompleted(e) => {
+                ("OnWorkRequestCompleted", serde_json::to_string(e))
             }
-        } else {
-            return None;
+            ContractEvent::OnNewWorkRequestBid(e) => {
+                ("OnNewWorkRequestBid", serde_json::to_string(e))
+            }
+            ContractEvent::OnBidWon(e) => ("OnBidWon", serde_json::to_string(e)),
+            ContractEvent::OnAuctionFailure(e) => ("OnAuctionFailure", serde_json::to_string(e)),
+            ContractEvent::Unknown(e) => (e.name.as_str(), Ok(e.data.to_owned())),
+        };
+
+        RawEvent {
+            version: self.version,
+            timestamp_us: self.timestamp_us,
+            address: self.address.to_owned(),
+            module: self.module.to_owned(),
+            name: name.to_owned(),
+            // plain structs of strings and integers always serialize
+            data: data.unwrap(),
         }
     }
 }
 
+pub trait ContractEventExtractor where Self: Sized {
+    /// Returns `None` if the event was not emitted by one of the watched contracts.
+    fn extract_event_data_with_filters(
+        event: &RawEvent,
+        filter: &EventFilter,
+    ) -> Option<Result<Self, DecodeError>>;
+
+    fn extract_event_data(event: &RawEvent) -> Result<Self, DecodeError>;
+}
 
+fn decode<T: DeserializeOwned>(
+    event: &RawEvent,
+    variant: fn(T) -> ContractEvent,
+) -> Result<ContractEvent, DecodeError> {
+    serde_json::from_str(&event.data)
+        .map(variant)
+        .map_err(|e| DecodeError {
+            event: event.to_owned(),
+            error: e.to_string(),
+        })
+}
 
+impl ContractEventExtractor for ContractEvent {
+    fn extract_event_data_with_filters(
+        event: &RawEvent,
+        filter: &EventFilter,
+    ) -> Option<Result<Self, DecodeError>> {
+        if !filter.matches(event) {
+            return None;
+        }
+
+        Some(ContractEvent::extract_event_data(event))
+    }
+
+    fn extract_event_data(event: &RawEvent) -> Result<Self, DecodeError> {
+        match event.name.as_str() {
+            "OnNewWorkRequest" => decode(event, ContractEvent::OnNewWorkRequest),
+            "OnWorkRequestCompleted" => decode(event, ContractEvent::OnWorkRequestCompleted),
+            "OnNewWorkRequestBid" => decode(event, ContractEvent::OnNewWorkRequestBid),
+            "OnBidWon" => decode(event, ContractEvent::OnBidWon),
+            "OnAuctionFailure" => decode(event, ContractEvent::OnAuctionFailure),
+            _ => Ok(ContractEvent::Unknown(UnknownEvent {
+                name: event.name.to_owned(),
+                data: event.data.to_owned(),
+            })),
+        }
+    }
+}
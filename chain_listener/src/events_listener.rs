@@ -1,157 +1,133 @@
-use std::str::FromStr;
-use std::sync::Arc;
+use std::time::Duration;
 
-use aptos_protos::indexer::v1::raw_data_client::RawDataClient;
-use aptos_protos::indexer::v1::GetTransactionsRequest;
-use aptos_protos::transaction::v1::transaction::TxnData;
-use aptos_protos::transaction::v1::{BlockMetadataTransaction, Event, UserTransaction};
-use aptos_sdk::move_types::language_storage::ModuleId;
 use proxirun_sdk::events::ContractEvent;
 use rayon::iter::{IntoParallelRefIterator, ParallelIterator};
 use tokio::sync::mpsc::UnboundedSender;
-use tokio::sync::Mutex;
-use tokio_stream::StreamExt;
-use tonic::service::Interceptor;
-use tonic::transport::Channel;
+use tokio::time::sleep;
 
-use crate::events::ContractEventExtractor;
+use crate::event_sender::EventSender;
+use crate::event_source::{EventSource, GrpcEventSource, RawEvent};
+use crate::events::{ContractEventExtractor, DecodeError, EventEnvelope};
+use crate::filter::EventFilter;
+use crate::stats::ListenerStats;
 
-use tonic::metadata::MetadataValue;
-use tonic::{IntoRequest, Request, Status};
+const RETRY_DELAY: Duration = Duration::from_secs(1);
 
-#[derive(Clone)]
-struct AuthInterceptor {
-    pub token: String,
+/// Listens to the Aptos Transaction Stream Service, see `run_listener_with_source`.
+pub async fn run_listener(
+    api_key: &str,
+    indexer_url: &str,
+    contracts: impl Into<EventFilter>,
+    sender_events: impl Into<EventSender<ContractEvent>>,
+    sender_decode_errors: UnboundedSender<DecodeError>,
+) -> Result<ListenerStats, Box<dyn std::error::Error>> {
+    run_listener_with_source(
+        GrpcEventSource::new(api_key, indexer_url),
+        contracts,
+        sender_events,
+        sender_decode_errors,
+    )
+    .await
 }
 
-impl Interceptor for AuthInterceptor {
-    fn call(&mut self, mut request: Request<()>) -> Result<Request<()>, tonic::Status> {
-        // Add the authorization header with the token
-        let token = format!("Bearer {}", self.token);
-        let metadata_value = MetadataValue::from_str(&token)
-            .map_err(|_| tonic::Status::unauthenticated("Invalid token"))?;
-        request
-            .metadata_mut()
-            .insert("authorization", metadata_value);
-
-        Ok(request)
-    }
+/// Spawns a task that reads events from `source` and pushes the ones emitted by
+/// the watched `contracts` into `sender_events`.
+/// Events that fail to decode are reported on `sender_decode_errors` and skipped.
+///
+/// When `sender_events` is bounded, the source is not read while the channel is full.
+/// The returned stats follow the progress of the listener.
+pub async fn run_listener_with_source<S: EventSource + 'static>(
+    source: S,
+    contracts: impl Into<EventFilter>,
+    sender_events: impl Into<EventSender<ContractEvent>>,
+    sender_decode_errors: UnboundedSender<DecodeError>,
+) -> Result<ListenerStats, Box<dyn std::error::Error>> {
+    spawn_listener(
+        source,
+        contracts.into(),
+        sender_events.into(),
+        sender_decode_errors,
+        |_, event| event,
+    )
 }
 
-/*
-fn intercept(req: Request<()>) -> Result<Request<()>, Status> {
-    let mut req = req.into_request();
-    let token = format!("Bearer {}", API_KEY);
-    let metadata_value =
-        MetadataValue::from_str(&token).map_err(|_| Status::unauthenticated("Invalid token"))?;
-    req.metadata_mut().insert("authorization", metadata_value);
-    Ok(req)
+/// `run_listener_with_source` pushing each event in its `EventEnvelope`, for consumers that
+/// need the version or the chain timestamp of the events, e.g. after catching up on a backlog.
+pub async fn run_envelope_listener_with_source<S: EventSource + 'static>(
+    source: S,
+    contracts: impl Into<EventFilter>,
+    sender_events: impl Into<EventSender<EventEnvelope>>,
+    sender_decode_errors: UnboundedSender<DecodeError>,
+) -> Result<ListenerStats, Box<dyn std::error::Error>> {
+    spawn_listener(
+        source,
+        contracts.into(),
+        sender_events.into(),
+        sender_decode_errors,
+        EventEnvelope::new,
+    )
 }
-*/
 
-pub async fn run_listener(
-    api_key: &str,
-    indexer_url: &str,
-    module_id: ModuleId,
-    sender_events: UnboundedSender<ContractEvent>,
-    //sender_new_work_request: UnboundedSender<OnNewWorkRequest>,
-    //sender_on_bid_won: UnboundedSender<OnBidWon>,
-) -> Result<(), Box<dyn std::error::Error>> {
+fn spawn_listener<S: EventSource + 'static, T: Send + 'static>(
+    mut source: S,
+    filter: EventFilter,
+    sender_events: EventSender<T>,
+    sender_decode_errors: UnboundedSender<DecodeError>,
+    wrap: fn(&RawEvent, ContractEvent) -> T,
+) -> Result<ListenerStats, Box<dyn std::error::Error>> {
     println!("Starting chain listener");
-    // keep track of latest version in case client stops
-    let mut latest_version: Arc<Mutex<Option<u64>>> = Arc::new(Mutex::new(None));
 
-    let api_key = api_key.to_owned();
-    let indexer_url = indexer_url.to_owned();
-    let chain_listener = tokio::spawn(async move {
+    let stats = ListenerStats::new(sender_events.downgrade());
+    let task_stats = stats.clone();
+    let _chain_listener = tokio::spawn(async move {
         loop {
-            //let mut client = RawDataClient::connect(INDEXER_URL).await?;
-            let interceptor = AuthInterceptor {
-                token: api_key.to_owned(),
-            };
-
-            // Create a gRPC channel
-            let channel = Channel::from_shared(indexer_url.to_owned())
-                .unwrap()
-                .connect()
-                .await
-                .unwrap();
-            let mut client = RawDataClient::with_interceptor(channel, interceptor);
-
-            let curr_latest_version = {
-                let handle = latest_version.lock().await;
-                *handle
-            };
-
-            let req = GetTransactionsRequest {
-                starting_version: curr_latest_version,
-                transactions_count: None,
-                batch_size: None,
-            };
-            let response = client.get_transactions(req).await.unwrap();
-
-            let mut resp_stream = response.into_inner();
-
-            while let Some(received) = resp_stream.next().await {
-                let received = received.unwrap();
-
-                // update lastest received version
-                let mut temp_version = 0;
-                for tx in &received.transactions {
-                    if tx.version > temp_version {
-                        temp_version = tx.version;
-                    }
+            let batch = match source.next_batch().await {
+                Ok(Some(batch)) => batch,
+                Ok(None) => {
+                    println!("Chain listener has stopped: event source exhausted");
+                    break;
                 }
-
-                {
-                    let mut handle = latest_version.lock().await;
-                    *handle = Some(temp_version);
+                Err(e) => {
+                    println!("Chain listener error: {}", e);
+                    sleep(RETRY_DELAY).await;
+                    continue;
                 }
+            };
 
-                let filtered_events: Vec<Vec<Event>> = received
-                    .transactions
-                    .par_iter()
-                    .filter_map(|txn| {
-                        if let Some(txn_data) = &txn.txn_data {
-                            match txn_data {
-                                TxnData::User(data) => Some(data.events.to_owned()),
-                                _ => None,
-                            }
-                        } else {
-                            None
+            if let Some(chain_tip) = batch.chain_tip {
+                task_stats.set_chain_tip(chain_tip);
+            }
+            task_stats.add_transactions_scanned(batch.transactions);
+            task_stats.add_reconnects(batch.reconnects);
+
+            let filtered_event: Vec<(&RawEvent, Result<ContractEvent, DecodeError>)> = batch
+                .events
+                .par_iter()
+                .filter_map(|raw| {
+                    ContractEvent::extract_event_data_with_filters(raw, &filter).map(|e| (raw, e))
+                })
+                .collect();
+
+            for (raw, e) in filtered_event {
+                match e {
+                    Ok(e) => {
+                        task_stats.add_event_matched(&e);
+                        if sender_events.send(wrap(raw, e)).await.is_err() {
+                            println!("Chain listener has stopped: receiver dropped");
+                            return;
                         }
-                    })
-                    .collect();
-
-                let mut flattened: Vec<Event> = vec![];
-                for events in filtered_events {
-                    for e in events {
-                        flattened.push(e);
                     }
-                }
-
-                let filtered_event: Vec<ContractEvent> = flattened
-                    .par_iter()
-                    .filter_map(|e| {
-                        ContractEvent::extract_event_data_with_filters(
-                            e,
-                            &module_id.address.to_string(),
-                            &module_id.name.to_string(),
-                        )
-                    })
-                    .collect();
-
-                for e in filtered_event {
-                    sender_events.send(e).unwrap();
+                    Err(e) => {
+                        task_stats.add_decode_failure();
+                        // nobody listening for decode errors is not a reason to stop
+                        let _ = sender_decode_errors.send(e);
+                    }
                 }
             }
 
-            {
-                let handle = latest_version.lock().await;
-                println!("Chain listener has stopped: restarting from version {}", handle.unwrap());
-            }
+            task_stats.set_latest_version(batch.latest_version);
         }
     });
 
-    return Ok(());
+    Ok(stats)
 }
@@ -0,0 +1,49 @@
+use std::str::FromStr;
+
+use aptos_sdk::move_types::language_storage::ModuleId;
+use aptos_sdk::types::account_address::AccountAddress;
+
+use crate::event_source::RawEvent;
+
+/// Matches the events emitted by a set of deployed ProxiRun contracts.
+/// An event passes only if both the address and module of its type tag belong to a watched contract,
+/// structs the SDK does not know about are decoded as `ContractEvent::Unknown`.
+#[derive(Debug, Clone)]
+pub struct EventFilter {
+    contracts: Vec<ModuleId>,
+}
+
+impl EventFilter {
+    pub fn new(contracts: Vec<ModuleId>) -> Self {
+        EventFilter { contracts }
+    }
+
+    pub fn contracts(&self) -> &[ModuleId] {
+        &self.contracts
+    }
+
+    /// Returns the watched contract that emitted this event, if any.
+    pub fn matching_contract(&self, event: &RawEvent) -> Option<&ModuleId> {
+        // addresses may come in short or long form depending on the source
+        let address = AccountAddress::from_str(&event.address).ok()?;
+        self.contracts
+            .iter()
+            .find(|m| m.address == address && m.name.as_str() == event.module)
+    }
+
+    pub fn matches(&self, event: &RawEvent) -> bool {
+        self.matching_contract(event).is_some()
+    }
+}
+
+impl From<ModuleId> for EventFilter {
+    fn from(module_id: ModuleId) -> Self {
+        EventFilter::new(vec![module_id])
+    }
+}
+
+impl From<Vec<ModuleId>> for EventFilter {
+    fn from(contracts: Vec<ModuleId>) -> Self {
+        EventFilter::new(contracts)
+    }
+}
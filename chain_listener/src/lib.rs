@@ -1,2 +1,10 @@
+pub mod config;
+pub mod event_sender;
+pub mod event_source;
+pub mod event_stream;
 pub mod events;
 pub mod events_listener;
+pub mod filter;
+pub mod metrics;
+pub mod stats;
+pub mod subscriber;
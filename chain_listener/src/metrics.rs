@@ -0,0 +1,121 @@
+use std::fmt::Write;
+
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+use crate::stats::ListenerStats;
+
+/// Content type of the Prometheus text exposition format
+pub const PROMETHEUS_CONTENT_TYPE: &str = "text/plain; version=0.0.4";
+
+fn write_metric(out: &mut String, name: &str, kind: &str, help: &str, value: u64) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+/// Renders the listener stats in the Prometheus text format, so that binaries with
+/// an HTTP server can mount it on their own `/metrics` route.
+/// Gauges that are unknown, e.g. the chain tip before the source reported it, are left out.
+pub fn render_prometheus(stats: &ListenerStats) -> String {
+    let mut out = String::new();
+
+    write_metric(
+        &mut out,
+        "chain_listener_transactions_scanned_total",
+        "counter",
+        "Transactions read from the event source.",
+        stats.transactions_scanned(),
+    );
+
+    let _ = writeln!(
+        out,
+        "# HELP chain_listener_events_matched_total Events of the watched contracts, by type."
+    );
+    let _ = writeln!(out, "# TYPE chain_listener_events_matched_total counter");
+    for (event_type, count) in stats.events_matched() {
+        let _ = writeln!(
+            out,
+            "chain_listener_events_matched_total{{type=\"{}\"}} {}",
+            event_type, count
+        );
+    }
+
+    write_metric(
+        &mut out,
+        "chain_listener_decode_failures_total",
+        "counter",
+        "Events of the watched contracts that failed to decode.",
+        stats.decode_failures(),
+    );
+    write_metric(
+        &mut out,
+        "chain_listener_reconnects_total",
+        "counter",
+        "Reconnections of the event source.",
+        stats.reconnects(),
+    );
+    write_metric(
+        &mut out,
+        "chain_listener_latest_version",
+        "gauge",
+        "Highest transaction version processed.",
+        stats.latest_version(),
+    );
+    if let Some(chain_tip) = stats.chain_tip() {
+        write_metric(
+            &mut out,
+            "chain_listener_chain_tip",
+            "gauge",
+            "Latest version known to the chain.",
+            chain_tip,
+        );
+    }
+    if let Some(lag) = stats.version_lag() {
+        write_metric(
+            &mut out,
+            "chain_listener_version_lag",
+            "gauge",
+            "Transactions between the chain tip and the latest processed version.",
+            lag,
+        );
+    }
+    if let Some(depth) = stats.queue_depth() {
+        write_metric(
+            &mut out,
+            "chain_listener_queue_depth",
+            "gauge",
+            "Events waiting for consumers.",
+            depth as u64,
+        );
+    }
+
+    out
+}
+
+/// Serves `render_prometheus` on every request made to `addr`, for binaries without an HTTP server.
+pub async fn serve_metrics(addr: &str, stats: ListenerStats) -> std::io::Result<()> {
+    let listener = TcpListener::bind(addr).await?;
+    println!("Serving listener metrics on {}", addr);
+
+    loop {
+        let (mut socket, _) = listener.accept().await?;
+        let stats = stats.clone();
+        tokio::spawn(async move {
+            // the request itself does not matter, every path answers the metrics
+            let mut request = [0u8; 1024];
+            if socket.read(&mut request).await.is_err() {
+                return;
+            }
+
+            let body = render_prometheus(&stats);
+            let response = format!(
+                "HTTP/1.1 200 OK\r\nContent-Type: {}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+                PROMETHEUS_CONTENT_TYPE,
+                body.len(),
+                body
+            );
+            let _ = socket.write_all(response.as_bytes()).await;
+        });
+    }
+}
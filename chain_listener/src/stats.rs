@@ -0,0 +1,121 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use proxirun_sdk::events::ContractEvent;
+use tokio::sync::mpsc::WeakSender;
+
+/// Event types counted by `ListenerStats::events_matched`, unknown events are counted together
+pub const EVENT_TYPES: [&str; 6] = [
+    "OnNewWorkRequest",
+    "OnWorkRequestCompleted",
+    "OnNewWorkRequestBid",
+    "OnBidWon",
+    "OnAuctionFailure",
+    "Unknown",
+];
+
+fn event_type_index(event: &ContractEvent) -> usize {
+    match event {
+        ContractEvent::OnNewWorkRequest(_) => 0,
+        ContractEvent::OnWorkRequestCompleted(_) => 1,
+        ContractEvent::OnNewWorkRequestBid(_) => 2,
+        ContractEvent::OnBidWon(_) => 3,
+        ContractEvent::OnAuctionFailure(_) => 4,
+        ContractEvent::Unknown(_) => 5,
+    }
+}
+
+/// Live view on the progress of a running listener.
+#[derive(Clone, Default)]
+pub struct ListenerStats {
+    latest_version: Arc<AtomicU64>,
+    // 0 until the source reported the chain tip
+    chain_tip: Arc<AtomicU64>,
+    transactions_scanned: Arc<AtomicU64>,
+    // indexed like `EVENT_TYPES`
+    events_matched: Arc<[AtomicU64; 6]>,
+    decode_failures: Arc<AtomicU64>,
+    reconnects: Arc<AtomicU64>,
+    // depth of the channel, through a weak sender so that the stats do not keep it open
+    queue: Option<Arc<dyn Fn() -> Option<usize> + Send + Sync>>,
+}
+
+impl ListenerStats {
+    pub(crate) fn new<T: Send + 'static>(queue: Option<WeakSender<T>>) -> Self {
+        ListenerStats {
+            queue: queue.map(|queue| {
+                Arc::new(move || {
+                    let sender = queue.upgrade()?;
+                    Some(sender.max_capacity() - sender.capacity())
+                }) as Arc<dyn Fn() -> Option<usize> + Send + Sync>
+            }),
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn set_latest_version(&self, version: u64) {
+        self.latest_version.store(version, Ordering::Relaxed);
+    }
+
+    pub(crate) fn set_chain_tip(&self, version: u64) {
+        self.chain_tip.fetch_max(version, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_transactions_scanned(&self, count: u64) {
+        self.transactions_scanned.fetch_add(count, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_event_matched(&self, event: &ContractEvent) {
+        self.events_matched[event_type_index(event)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_decode_failure(&self) {
+        self.decode_failures.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn add_reconnects(&self, count: u64) {
+        self.reconnects.fetch_add(count, Ordering::Relaxed);
+    }
+
+    /// Highest transaction version processed by the listener
+    pub fn latest_version(&self) -> u64 {
+        self.latest_version.load(Ordering::Relaxed)
+    }
+
+    pub fn chain_tip(&self) -> Option<u64> {
+        match self.chain_tip.load(Ordering::Relaxed) {
+            0 => None,
+            version => Some(version),
+        }
+    }
+
+    /// Number of transactions between the chain tip and the last processed version
+    pub fn version_lag(&self) -> Option<u64> {
+        self.chain_tip()
+            .map(|tip| tip.saturating_sub(self.latest_version()))
+    }
+
+    /// Number of events waiting for consumers, only known for bounded channels
+    pub fn queue_depth(&self) -> Option<usize> {
+        (self.queue.as_ref()?)()
+    }
+
+    /// Number of transactions read from the source, matching or not
+    pub fn transactions_scanned(&self) -> u64 {
+        self.transactions_scanned.load(Ordering::Relaxed)
+    }
+
+    /// Number of events of the watched contracts per type, in the order of `EVENT_TYPES`
+    pub fn events_matched(&self) -> [(&'static str, u64); 6] {
+        std::array::from_fn(|i| (EVENT_TYPES[i], self.events_matched[i].load(Ordering::Relaxed)))
+    }
+
+    pub fn decode_failures(&self) -> u64 {
+        self.decode_failures.load(Ordering::Relaxed)
+    }
+
+    /// Number of times the source had to reconnect to the chain
+    pub fn reconnects(&self) -> u64 {
+        self.reconnects.load(Ordering::Relaxed)
+    }
+}
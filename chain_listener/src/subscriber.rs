@@ -0,0 +1,121 @@
+use std::marker::PhantomData;
+use std::sync::Arc;
+
+use proxirun_sdk::events::*;
+use tokio::sync::broadcast::{self, error::RecvError};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::event_source::EventSource;
+use crate::events::DecodeError;
+use crate::events_listener::run_listener_with_source;
+use crate::filter::EventFilter;
+use crate::stats::ListenerStats;
+
+/// A contract event type that can be subscribed to on its own.
+pub trait TypedEvent: Clone + Send + 'static {
+    fn from_contract_event(event: &ContractEvent) -> Option<&Self>;
+}
+
+impl TypedEvent for ContractEvent {
+    fn from_contract_event(event: &ContractEvent) -> Option<&Self> {
+        Some(event)
+    }
+}
+
+macro_rules! impl_typed_event {
+    ($($variant:ident),*) => {
+        $(
+            impl TypedEvent for $variant {
+                fn from_contract_event(event: &ContractEvent) -> Option<&Self> {
+                    match event {
+                        ContractEvent::$variant(e) => Some(e),
+                        _ => None,
+                    }
+                }
+            }
+        )*
+    };
+}
+
+impl_typed_event!(
+    OnNewWorkRequest,
+    OnWorkRequestCompleted,
+    OnNewWorkRequestBid,
+    OnBidWon,
+    OnAuctionFailure
+);
+
+/// Fans the events of a single event source out to any number of typed subscriptions.
+///
+/// Subscribe before calling `run` to not miss any event. Subscribers that fall more than
+/// `capacity` events behind skip the oldest ones.
+pub struct ChainListener {
+    sender: broadcast::Sender<Arc<ContractEvent>>,
+}
+
+impl ChainListener {
+    pub fn new(capacity: usize) -> Self {
+        let (sender, _) = broadcast::channel(capacity);
+        ChainListener { sender }
+    }
+
+    /// Receives every event of type `T`, e.g. `listener.subscribe::<OnBidWon>()`.
+    pub fn subscribe<T: TypedEvent>(&self) -> Subscription<T> {
+        self.subscribe_filtered(|_: &T| true)
+    }
+
+    /// Receives the events of type `T` for which `predicate` returns true.
+    pub fn subscribe_filtered<T: TypedEvent>(
+        &self,
+        predicate: impl Fn(&T) -> bool + Send + 'static,
+    ) -> Subscription<T> {
+        Subscription {
+            receiver: self.sender.subscribe(),
+            predicate: Box::new(predicate),
+            _event: PhantomData,
+        }
+    }
+
+    /// Starts reading events from `source`, see `run_listener_with_source`.
+    pub async fn run<S: EventSource + 'static>(
+        &self,
+        source: S,
+        contracts: impl Into<EventFilter>,
+        sender_decode_errors: UnboundedSender<DecodeError>,
+    ) -> Result<ListenerStats, Box<dyn std::error::Error>> {
+        run_listener_with_source(
+            source,
+            contracts,
+            self.sender.clone(),
+            sender_decode_errors,
+        )
+        .await
+    }
+}
+
+pub struct Subscription<T> {
+    receiver: broadcast::Receiver<Arc<ContractEvent>>,
+    predicate: Box<dyn Fn(&T) -> bool + Send>,
+    _event: PhantomData<fn() -> T>,
+}
+
+impl<T: TypedEvent> Subscription<T> {
+    /// Waits for the next matching event. Returns `None` once the listener is gone.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            match self.receiver.recv().await {
+                Ok(event) => {
+                    if let Some(event) = T::from_contract_event(&event) {
+                        if (self.predicate)(event) {
+                            return Some(event.to_owned());
+                        }
+                    }
+                }
+                Err(RecvError::Lagged(skipped)) => {
+                    println!("Subscriber fell behind, {} events skipped", skipped);
+                }
+                Err(RecvError::Closed) => return None,
+            }
+        }
+    }
+}
@@ -0,0 +1,17 @@
+-- Tables the orchestrator was first deployed with, kept idempotent so that
+-- existing databases can adopt the migrations without being recreated.
+
+-- Task definitions, written by the frontend when a request is created
+CREATE TABLE IF NOT EXISTS payloads (
+    request_id BIGINT PRIMARY KEY,
+    task_type TEXT NOT NULL,
+    data TEXT NOT NULL,
+    model TEXT NOT NULL,
+    requester TEXT NOT NULL
+);
+
+-- Results of the text generation tasks
+CREATE TABLE IF NOT EXISTS text_completions (
+    request_id BIGINT PRIMARY KEY,
+    content TEXT NOT NULL
+);
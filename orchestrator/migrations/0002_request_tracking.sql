@@ -0,0 +1,39 @@
+-- Current status of each request
+CREATE TABLE request_status (
+    request_id BIGINT PRIMARY KEY,
+    status TEXT NOT NULL,
+    winner TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- History of the status changes of each request
+CREATE TABLE request_transitions (
+    id BIGSERIAL PRIMARY KEY,
+    request_id BIGINT NOT NULL,
+    from_status TEXT,
+    to_status TEXT NOT NULL,
+    detail TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX request_transitions_request_id ON request_transitions (request_id);
+
+-- Bids placed on chain for each request
+CREATE TABLE bids (
+    id BIGSERIAL PRIMARY KEY,
+    request_id BIGINT NOT NULL,
+    bidder TEXT NOT NULL,
+    price BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX bids_request_id ON bids (request_id);
+
+-- Image and voice results submitted by the workers
+CREATE TABLE outputs (
+    request_id BIGINT PRIMARY KEY,
+    location TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
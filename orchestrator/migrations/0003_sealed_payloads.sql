@@ -0,0 +1,18 @@
+-- Requests whose payload is sealed to the winning worker, `data` then holds no prompt
+ALTER TABLE payloads ADD COLUMN encrypted BOOLEAN NOT NULL DEFAULT false;
+
+-- x25519 keys registered by the workers to receive sealed payloads
+CREATE TABLE worker_keys (
+    address TEXT PRIMARY KEY,
+    encryption_key TEXT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Payloads encrypted by the requesters to the winner's key, the orchestrator cannot read them
+CREATE TABLE sealed_payloads (
+    request_id BIGINT PRIMARY KEY,
+    ephemeral_key TEXT NOT NULL,
+    nonce TEXT NOT NULL,
+    ciphertext TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,13 @@
+-- SHA-256 of the stored output, hex encoded
+ALTER TABLE outputs ADD COLUMN content_hash TEXT NOT NULL;
+
+-- Delivery receipts signed by the orchestrator, binding the output hash to the commit transaction
+CREATE TABLE receipts (
+    request_id BIGINT PRIMARY KEY,
+    content_hash TEXT NOT NULL,
+    commit_transaction TEXT NOT NULL,
+    orchestrator TEXT NOT NULL,
+    public_key TEXT NOT NULL,
+    signature TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,14 @@
+-- Stored files, addressed by their SHA-256: identical results are stored once
+CREATE TABLE blobs (
+    content_hash TEXT PRIMARY KEY,
+    location TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+INSERT INTO blobs (content_hash, location, size)
+SELECT DISTINCT ON (content_hash) content_hash, location, size FROM outputs ORDER BY content_hash, created_at;
+
+-- outputs now maps each request to the blob of its result
+ALTER TABLE outputs DROP COLUMN location, DROP COLUMN size;
+CREATE INDEX outputs_content_hash ON outputs (content_hash);
@@ -0,0 +1,11 @@
+-- Derived versions of the stored files, such as converted or resized images, cached in the result store
+CREATE TABLE variants (
+    source_hash TEXT NOT NULL,
+    variant TEXT NOT NULL,
+    location TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    content_hash TEXT NOT NULL,
+    size BIGINT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (source_hash, variant)
+);
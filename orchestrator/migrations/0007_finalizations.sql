@@ -0,0 +1,12 @@
+-- Auction finalizations, kept so that the ones pending when the orchestrator stops are resumed at boot
+CREATE TABLE finalizations (
+    request_id BIGINT PRIMARY KEY,
+    due_at TIMESTAMPTZ NOT NULL,
+    -- pending, done or failed
+    state TEXT NOT NULL DEFAULT 'pending',
+    attempts INT NOT NULL DEFAULT 0,
+    detail TEXT,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX finalizations_pending ON finalizations (due_at) WHERE state = 'pending';
@@ -0,0 +1,11 @@
+-- Deadline of the winner of each auction to submit its result
+CREATE TABLE delivery_deadlines (
+    request_id BIGINT PRIMARY KEY,
+    winner TEXT NOT NULL,
+    due_at TIMESTAMPTZ NOT NULL,
+    -- pending, delivered or missed
+    state TEXT NOT NULL DEFAULT 'pending',
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+CREATE INDEX delivery_deadlines_pending ON delivery_deadlines (due_at) WHERE state = 'pending';
@@ -0,0 +1,6 @@
+-- Bids are received again when events are replayed, keep a single row per bid
+DELETE FROM bids a USING bids b
+WHERE a.id > b.id AND a.request_id = b.request_id AND a.bidder = b.bidder AND a.price = b.price;
+
+CREATE UNIQUE INDEX bids_unique ON bids (request_id, bidder, price);
+CREATE INDEX bids_bidder ON bids (bidder);
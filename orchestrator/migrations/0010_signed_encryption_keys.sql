@@ -0,0 +1,7 @@
+-- The registrations are kept signed, so that requesters check the keys against the workers' accounts.
+-- Unsigned keys cannot be handed out anymore, the workers register again when they start.
+DELETE FROM worker_keys;
+
+ALTER TABLE worker_keys ADD COLUMN registered_at BIGINT NOT NULL;
+ALTER TABLE worker_keys ADD COLUMN public_key TEXT NOT NULL;
+ALTER TABLE worker_keys ADD COLUMN signature TEXT NOT NULL;
@@ -0,0 +1,8 @@
+-- Last contract event handled, so that a restart resumes the chain where the previous run stopped
+CREATE TABLE listener_cursor (
+    id SMALLINT PRIMARY KEY DEFAULT 1 CHECK (id = 1),
+    version BIGINT NOT NULL,
+    -- events of the transaction `version` already handled
+    events BIGINT NOT NULL,
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
@@ -0,0 +1,6 @@
+-- Delivery timeouts are kept until the transaction is executed, and sent again at boot until then.
+-- The states are now pending, delivered, missed (no timeout function), timeout_pending or timed_out
+ALTER TABLE delivery_deadlines ADD COLUMN attempts INT NOT NULL DEFAULT 0;
+ALTER TABLE delivery_deadlines ADD COLUMN detail TEXT;
+
+CREATE INDEX delivery_deadlines_timeouts ON delivery_deadlines (request_id) WHERE state = 'timeout_pending';
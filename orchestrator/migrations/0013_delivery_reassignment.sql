@@ -0,0 +1,8 @@
+-- Winners that missed the delivery deadline of a request, never handed that request again.
+-- Deadlines can now also be reassign_pending, until the contract hands the request to the runner-up
+CREATE TABLE delivery_misses (
+    request_id BIGINT NOT NULL,
+    winner TEXT NOT NULL,
+    missed_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (request_id, winner)
+);
@@ -0,0 +1,311 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use actix_web::{error, HttpRequest};
+use aptos_sdk::crypto::ed25519::Ed25519PublicKey;
+use aptos_sdk::types::account_address::AccountAddress;
+use aptos_sdk::types::transaction::authenticator::AuthenticationKey;
+use proxirun_sdk::submission::{
+    content_hash, derived_address, verify_encryption_key, verify_payload_access,
+    verify_sealed_payload, verify_submission, EncryptionKeyRegistration, PayloadChallenge,
+    SubmissionSignature, NONCE_HEADER, PUBLIC_KEY_HEADER, SIGNATURE_HEADER,
+};
+use rand::rngs::OsRng;
+use rand::RngCore;
+use tokio::time::Instant;
+
+use crate::lifecycle::RequestStatus;
+use crate::receipts::fetch_receipt;
+use crate::AppState;
+
+const CHALLENGE_TTL: Duration = Duration::from_secs(60);
+// outstanding challenges of a request, a reader only needs one at a time
+const MAX_CHALLENGES_PER_REQUEST: usize = 8;
+// an account can rotate its key, the cached one is refreshed after that
+const AUTH_KEY_TTL: Duration = Duration::from_secs(30);
+// key registrations are signed right before they are sent
+const REGISTRATION_MAX_AGE: Duration = Duration::from_secs(300);
+const MAX_CLOCK_SKEW: Duration = Duration::from_secs(30);
+
+/// Nonces handed out for the payload challenges, each can be answered once before it expires.
+#[derive(Clone, Default)]
+pub struct ChallengeStore {
+    // nonce -> (request id, expiry)
+    nonces: Arc<Mutex<HashMap<String, (u64, Instant)>>>,
+}
+
+impl ChallengeStore {
+    /// New nonce for `request_id`, `None` when the request has too many outstanding ones.
+    pub fn issue(&self, request_id: u64) -> Option<PayloadChallenge> {
+        let now = Instant::now();
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces.retain(|_, (_, expiry)| *expiry > now);
+        let outstanding = nonces
+            .values()
+            .filter(|(issued_for, _)| *issued_for == request_id)
+            .count();
+        if outstanding >= MAX_CHALLENGES_PER_REQUEST {
+            return None;
+        }
+
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        let nonce = hex::encode(bytes);
+        nonces.insert(nonce.to_owned(), (request_id, now + CHALLENGE_TTL));
+
+        Some(PayloadChallenge {
+            nonce,
+            expires_in_secs: CHALLENGE_TTL.as_secs(),
+        })
+    }
+
+    /// Consumes the nonce, returns false if it was not issued for `request_id` or has expired.
+    fn redeem(&self, request_id: u64, nonce: &str) -> bool {
+        match self.nonces.lock().unwrap().remove(nonce) {
+            Some((issued_for, expiry)) => issued_for == request_id && expiry > Instant::now(),
+            None => false,
+        }
+    }
+}
+
+/// On-chain authentication keys of the accounts, so that a key that is not the account's
+/// does not cost a fullnode round-trip each time it is presented.
+#[derive(Clone, Default)]
+pub struct AuthKeyCache {
+    // address -> (authentication key, time it was read)
+    keys: Arc<Mutex<HashMap<AccountAddress, (AuthenticationKey, Instant)>>>,
+}
+
+impl AuthKeyCache {
+    fn get(&self, address: &AccountAddress) -> Option<AuthenticationKey> {
+        let now = Instant::now();
+        let mut keys = self.keys.lock().unwrap();
+        keys.retain(|_, (_, read_at)| now.duration_since(*read_at) < AUTH_KEY_TTL);
+        keys.get(address).map(|(key, _)| key.to_owned())
+    }
+
+    fn insert(&self, address: AccountAddress, key: AuthenticationKey) {
+        self.keys
+            .lock()
+            .unwrap()
+            .insert(address, (key, Instant::now()));
+    }
+}
+
+fn header(req: &HttpRequest, name: &str) -> Option<String> {
+    Some(req.headers().get(name)?.to_str().ok()?.to_owned())
+}
+
+fn signature_headers(req: &HttpRequest) -> Result<SubmissionSignature, actix_web::Error> {
+    match (header(req, PUBLIC_KEY_HEADER), header(req, SIGNATURE_HEADER)) {
+        (Some(public_key), Some(signature)) => Ok(SubmissionSignature {
+            public_key,
+            signature,
+        }),
+        _ => Err(error::ErrorUnauthorized("Missing signature")),
+    }
+}
+
+/// Whether `public_key` controls the account at `address`.
+async fn is_account_key(
+    app_state: &AppState,
+    address: &str,
+    public_key: &Ed25519PublicKey,
+) -> Result<bool, actix_web::Error> {
+    let address = AccountAddress::from_str(address).map_err(error::ErrorBadRequest)?;
+    if derived_address(public_key) == address {
+        return Ok(true);
+    }
+
+    // the account may have rotated its key since it was created
+    let authentication_key = match app_state.auth_keys.get(&address) {
+        Some(key) => key,
+        None => {
+            let account = app_state
+                .rest_client
+                .get_account(address)
+                .await
+                .map_err(error::ErrorInternalServerError)?;
+            let key = account.inner().authentication_key.to_owned();
+            app_state.auth_keys.insert(address, key.to_owned());
+            key
+        }
+    };
+
+    Ok(authentication_key == AuthenticationKey::ed25519(public_key))
+}
+
+/// Rejects the key unless it belongs to the winner of the auction of `request_id`.
+async fn check_winner(
+    app_state: &AppState,
+    request_id: u64,
+    public_key: &Ed25519PublicKey,
+) -> Result<(), actix_web::Error> {
+    let winner = match app_state.tracker.winner(request_id).await {
+        Ok(Some(winner)) => winner,
+        Ok(None) => return Err(error::ErrorConflict("The auction has no winner yet")),
+        Err(e) => return Err(error::ErrorInternalServerError(e)),
+    };
+    if is_account_key(app_state, &winner, public_key).await? {
+        return Ok(());
+    }
+
+    println!("Request {}: rejected a key that is not the winner's", request_id);
+    Err(error::ErrorForbidden("Not signed by the auction winner"))
+}
+
+/// A submission accepted by `verify_submitter`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Submission {
+    /// The result of an assigned request, to store and commit
+    New,
+    /// The result of a submitted request whose first result could not be stored, to store and commit
+    Unstored,
+    /// The result already stored, sent again because its commit failed
+    Retry,
+}
+
+impl Submission {
+    pub fn needs_storing(&self) -> bool {
+        !matches!(self, Submission::Retry)
+    }
+}
+
+/// Hex encoded SHA-256 of the result stored for the request, if any.
+async fn stored_hash(app_state: &AppState, request_id: u64) -> Result<Option<String>, sqlx::Error> {
+    let output: Option<(String,)> =
+        sqlx::query_as("SELECT content_hash from outputs where request_id=$1;")
+            .bind(request_id as i64)
+            .fetch_optional(&app_state.db_pool)
+            .await?;
+    if let Some((hash,)) = output {
+        return Ok(Some(hash));
+    }
+
+    let completion: Option<(String,)> =
+        sqlx::query_as("SELECT content from text_completions where request_id=$1;")
+            .bind(request_id as i64)
+            .fetch_optional(&app_state.db_pool)
+            .await?;
+
+    Ok(completion.map(|(content,)| hex::encode(content_hash(content.as_bytes()))))
+}
+
+/// How `content` can be sent again for a submitted request whose commit has not gone through:
+/// as the result already stored, or as the result the previous submission failed to store.
+async fn commit_retry(
+    app_state: &AppState,
+    request_id: u64,
+    content: &[u8],
+) -> Result<Option<Submission>, actix_web::Error> {
+    let receipt = fetch_receipt(&app_state.db_pool, request_id)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    if receipt.is_some() {
+        return Ok(None);
+    }
+    let stored = stored_hash(app_state, request_id)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    Ok(match stored {
+        None => Some(Submission::Unstored),
+        Some(stored) if stored == hex::encode(content_hash(content)) => Some(Submission::Retry),
+        Some(_) => None,
+    })
+}
+
+/// Rejects the submission of `content` unless it is signed by the winner of the auction of `request_id`
+/// and the request is still waiting for its result. The result of a failed commit can be sent again as is.
+pub async fn verify_submitter(
+    app_state: &AppState,
+    request_id: u64,
+    content: &[u8],
+    req: &HttpRequest,
+) -> Result<Submission, actix_web::Error> {
+    let signature = signature_headers(req)?;
+    let public_key =
+        verify_submission(request_id, content, &signature).map_err(error::ErrorUnauthorized)?;
+    check_winner(app_state, request_id, &public_key).await?;
+
+    // a submitted request is not resubmitted, and the winner may have missed its delivery deadline
+    match app_state.tracker.status(request_id).await {
+        Ok(Some(RequestStatus::Assigned(_))) => Ok(Submission::New),
+        Ok(Some(RequestStatus::Submitted)) => commit_retry(app_state, request_id, content)
+            .await?
+            .ok_or_else(|| error::ErrorConflict("The request is Submitted")),
+        Ok(Some(status)) => Err(error::ErrorConflict(format!(
+            "The request is {}",
+            status.name()
+        ))),
+        Ok(None) => Err(error::ErrorConflict("The auction has no winner yet")),
+        Err(e) => Err(error::ErrorInternalServerError(e)),
+    }
+}
+
+/// Rejects the payload read unless it answers a challenge of `request_id` with the winner's key.
+pub async fn verify_payload_reader(
+    app_state: &AppState,
+    request_id: u64,
+    req: &HttpRequest,
+) -> Result<(), actix_web::Error> {
+    let signature = signature_headers(req)?;
+    let nonce = header(req, NONCE_HEADER).ok_or(error::ErrorUnauthorized("Missing nonce"))?;
+    if !app_state.challenges.redeem(request_id, &nonce) {
+        return Err(error::ErrorUnauthorized("Unknown or expired nonce"));
+    }
+    let public_key =
+        verify_payload_access(request_id, &nonce, &signature).map_err(error::ErrorUnauthorized)?;
+
+    check_winner(app_state, request_id, &public_key).await
+}
+
+/// Rejects the registration unless it is recent and signed by the key of the account it registers for.
+pub async fn verify_key_registration(
+    app_state: &AppState,
+    registration: &EncryptionKeyRegistration,
+) -> Result<(), actix_web::Error> {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    if registration.registered_at + REGISTRATION_MAX_AGE.as_millis() as u64 <= now
+        || registration.registered_at > now + MAX_CLOCK_SKEW.as_millis() as u64
+    {
+        return Err(error::ErrorUnauthorized("Stale registration"));
+    }
+
+    let public_key = verify_encryption_key(registration).map_err(error::ErrorUnauthorized)?;
+    if is_account_key(app_state, &registration.address, &public_key).await? {
+        return Ok(());
+    }
+
+    Err(error::ErrorForbidden("Not signed by the registered account"))
+}
+
+/// Rejects the sealed payload `body` unless it is signed by the requester of `request_id`.
+pub async fn verify_requester(
+    app_state: &AppState,
+    request_id: u64,
+    body: &[u8],
+    req: &HttpRequest,
+) -> Result<(), actix_web::Error> {
+    let signature = signature_headers(req)?;
+    let public_key =
+        verify_sealed_payload(request_id, body, &signature).map_err(error::ErrorUnauthorized)?;
+
+    let requester: Option<(String,)> =
+        sqlx::query_as("SELECT requester from payloads where request_id=$1;")
+            .bind(request_id as i64)
+            .fetch_optional(&app_state.db_pool)
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+    let requester = match requester {
+        Some((requester,)) => requester,
+        None => return Err(error::ErrorNotFound("Unknown request")),
+    };
+    if is_account_key(app_state, &requester, &public_key).await? {
+        return Ok(());
+    }
+
+    Err(error::ErrorForbidden("Not signed by the requester"))
+}
@@ -0,0 +1,46 @@
+use sqlx::{Pool, Postgres};
+
+/// Position of the last contract event handled: the version of its transaction and the
+/// number of events of that transaction handled so far.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Cursor {
+    pub version: u64,
+    pub events: u64,
+}
+
+impl Cursor {
+    /// Moves the cursor past an event of transaction `version`.
+    pub fn advance(&mut self, version: u64) {
+        if version == self.version {
+            self.events += 1;
+        } else {
+            *self = Cursor { version, events: 1 };
+        }
+    }
+}
+
+/// Cursor saved by the previous run, `None` on the first run.
+pub async fn load_cursor(pool: &Pool<Postgres>) -> Result<Option<Cursor>, sqlx::Error> {
+    let row: Option<(i64, i64)> =
+        sqlx::query_as("SELECT version, events from listener_cursor where id=1;")
+            .fetch_optional(pool)
+            .await?;
+
+    Ok(row.map(|(version, events)| Cursor {
+        version: version as u64,
+        events: events as u64,
+    }))
+}
+
+pub async fn save_cursor(pool: &Pool<Postgres>, cursor: &Cursor) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT into listener_cursor (id, version, events) values (1, $1, $2) \
+         ON CONFLICT (id) DO UPDATE SET version=$1, events=$2, updated_at=now();",
+    )
+    .bind(cursor.version as i64)
+    .bind(cursor.events as i64)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
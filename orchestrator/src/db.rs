@@ -0,0 +1,10 @@
+use sqlx::migrate::{MigrateError, Migrator};
+use sqlx::{Pool, Postgres};
+
+/// Migrations of the `migrations` folder, embedded at compile time
+static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// Applies the migrations the database has not seen yet.
+pub async fn run_migrations(pool: &Pool<Postgres>) -> Result<(), MigrateError> {
+    MIGRATOR.run(pool).await
+}
@@ -0,0 +1,520 @@
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use aptos_sdk::move_types::identifier::Identifier;
+use aptos_sdk::rest_client::{Client, Transaction};
+use aptos_sdk::types::account_address::AccountAddress;
+use aptos_sdk::types::LocalAccount;
+use proxirun_sdk::contract_interact::{call_request_account_function, call_request_function};
+use sqlx::{Pool, Postgres};
+use tokio::time::sleep;
+
+use crate::lifecycle::{RequestStatus, RequestTracker};
+
+const DEFAULT_TEXT_DEADLINE: Duration = Duration::from_secs(120);
+const DEFAULT_IMAGE_DEADLINE: Duration = Duration::from_secs(300);
+const DEFAULT_VOICE_DEADLINE: Duration = Duration::from_secs(300);
+
+// backoff of the database and chain retries
+const RETRY_DELAY: Duration = Duration::from_secs(5);
+const MAX_RETRY_DELAY: Duration = Duration::from_secs(300);
+// attempts at a transaction sent for a missed deadline before giving up on it
+const MAX_SEND_TRIES: i32 = 5;
+
+/// Time given to the winner of an auction to submit its result, per task type.
+/// From `DELIVERY_DEADLINE_TEXT`, `DELIVERY_DEADLINE_IMAGE` and `DELIVERY_DEADLINE_VOICE` (seconds).
+/// `DELIVERY_TIMEOUT_FUNCTION` optionally names the entry function of the contract, taking
+/// the request id, that refunds the requester and slashes the winner once a deadline is missed.
+/// `DELIVERY_REASSIGN_FUNCTION` optionally names the entry function, taking the request id and
+/// the address of a bidder, that hands the request to that bidder instead.
+#[derive(Debug, Clone)]
+pub struct DeadlineConfig {
+    pub text: Duration,
+    pub image: Duration,
+    pub voice: Duration,
+    pub timeout_function: Option<Identifier>,
+    pub reassign_function: Option<Identifier>,
+}
+
+impl DeadlineConfig {
+    pub fn from_env() -> Self {
+        let deadline = |name: &str, default: Duration| {
+            std::env::var(name)
+                .map(|secs| {
+                    Duration::from_secs(
+                        secs.parse()
+                            .unwrap_or_else(|_| panic!("{} must be a number of seconds.", name)),
+                    )
+                })
+                .unwrap_or(default)
+        };
+        let timeout_function = std::env::var("DELIVERY_TIMEOUT_FUNCTION").ok().map(|function| {
+            Identifier::new(function).expect("DELIVERY_TIMEOUT_FUNCTION must be a Move identifier.")
+        });
+        let reassign_function = std::env::var("DELIVERY_REASSIGN_FUNCTION").ok().map(|function| {
+            Identifier::new(function).expect("DELIVERY_REASSIGN_FUNCTION must be a Move identifier.")
+        });
+
+        DeadlineConfig {
+            text: deadline("DELIVERY_DEADLINE_TEXT", DEFAULT_TEXT_DEADLINE),
+            image: deadline("DELIVERY_DEADLINE_IMAGE", DEFAULT_IMAGE_DEADLINE),
+            voice: deadline("DELIVERY_DEADLINE_VOICE", DEFAULT_VOICE_DEADLINE),
+            timeout_function,
+            reassign_function,
+        }
+    }
+
+    /// Deadline of a task type, the longest one for a type the orchestrator does not know
+    pub fn for_task_type(&self, task_type: Option<&str>) -> Duration {
+        match task_type {
+            Some("Text Generation") => self.text,
+            Some("Image Generation") => self.image,
+            Some("Voice Generation") => self.voice,
+            _ => self.text.max(self.image).max(self.voice),
+        }
+    }
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as u64
+}
+
+// transaction sent once a deadline is missed
+enum MissedDeadlineCall {
+    Timeout(Identifier),
+    Reassign(Identifier, AccountAddress),
+}
+
+/// Fails the requests whose winner does not submit in time, or hands them to the runner-up bidder.
+/// Deadlines are stored so that a restart keeps them.
+#[derive(Clone)]
+pub struct DeliveryDeadlines {
+    pool: Pool<Postgres>,
+    account: Arc<LocalAccount>,
+    rest_client: Arc<Client>,
+    tracker: RequestTracker,
+    config: Arc<DeadlineConfig>,
+}
+
+impl DeliveryDeadlines {
+    pub fn new(
+        pool: Pool<Postgres>,
+        account: Arc<LocalAccount>,
+        rest_client: Arc<Client>,
+        tracker: RequestTracker,
+        config: DeadlineConfig,
+    ) -> Self {
+        DeliveryDeadlines {
+            pool,
+            account,
+            rest_client,
+            tracker,
+            config: Arc::new(config),
+        }
+    }
+
+    /// Starts the deadline of `winner` for `request_id`, from `won_at_micros`, the chain timestamp
+    /// of `OnBidWon`, so that an event received late does not extend the deadline.
+    pub async fn start(
+        &self,
+        request_id: u64,
+        winner: &str,
+        won_at_micros: u64,
+    ) -> Result<(), sqlx::Error> {
+        let deadline = self.deadline_for(request_id).await?;
+        let due_at_micros = won_at_micros + deadline.as_micros() as u64;
+
+        let res = sqlx::query(
+            "INSERT into delivery_deadlines (request_id, winner, due_at) values ($1, $2, to_timestamp($3 / 1000000.0)) \
+             ON CONFLICT (request_id) DO NOTHING;",
+        )
+        .bind(request_id as i64)
+        .bind(winner)
+        .bind(due_at_micros as i64)
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() > 0 {
+            println!(
+                "Request {}: {} has {:?} to deliver",
+                request_id,
+                winner,
+                Duration::from_micros(due_at_micros.saturating_sub(now_micros()))
+            );
+            self.arm(request_id, due_at_micros);
+        }
+        Ok(())
+    }
+
+    async fn deadline_for(&self, request_id: u64) -> Result<Duration, sqlx::Error> {
+        let task_type: Option<(String,)> =
+            sqlx::query_as("SELECT task_type from payloads where request_id=$1;")
+                .bind(request_id as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(self
+            .config
+            .for_task_type(task_type.as_ref().map(|(task_type,)| task_type.as_str())))
+    }
+
+    /// Re-arms the deadlines left pending by the previous run, the overdue ones expire right away.
+    /// The delivery timeouts and reassignments that were not executed yet are sent again.
+    pub async fn resume(&self) -> Result<(), sqlx::Error> {
+        let pending: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT request_id, (extract(epoch from due_at) * 1000000)::BIGINT from delivery_deadlines \
+             where state='pending' order by due_at;",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        println!("Resuming {} pending delivery deadlines", pending.len());
+
+        for (request_id, due_at_micros) in pending {
+            self.arm(request_id as u64, due_at_micros as u64);
+        }
+
+        let missed: Vec<(i64, String)> = sqlx::query_as(
+            "SELECT request_id, state from delivery_deadlines \
+             where state in ('timeout_pending', 'reassign_pending') order by due_at;",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        println!("Resuming {} missed delivery deadlines", missed.len());
+
+        for (request_id, state) in missed {
+            let deadlines = self.clone();
+            tokio::spawn(async move {
+                match state.as_str() {
+                    "reassign_pending" => deadlines.send_reassign(request_id as u64).await,
+                    _ => deadlines.send_timeout(request_id as u64).await,
+                }
+            });
+        }
+        Ok(())
+    }
+
+    /// Stops the deadline of `request_id`, its result was submitted.
+    pub async fn delivered(&self, request_id: u64) {
+        self.record(request_id, "delivered").await;
+    }
+
+    async fn record(&self, request_id: u64, state: &str) {
+        self.set_state(request_id, "pending", state).await;
+    }
+
+    // moves the deadline to `to` if it is still in state `from`
+    async fn set_state(&self, request_id: u64, from: &str, to: &str) {
+        let res = sqlx::query(
+            "UPDATE delivery_deadlines SET state=$3, updated_at=now() where request_id=$1 and state=$2;",
+        )
+        .bind(request_id as i64)
+        .bind(from)
+        .bind(to)
+        .execute(&self.pool)
+        .await;
+
+        if let Err(e) = res {
+            println!("Request {}: failed to record delivery: {}", request_id, e);
+        }
+    }
+
+    fn arm(&self, request_id: u64, due_at_micros: u64) {
+        let deadlines = self.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_micros(due_at_micros.saturating_sub(now_micros()))).await;
+            deadlines.expire(request_id).await;
+        });
+    }
+
+    async fn expire(&self, request_id: u64) {
+        let runner_up = match &self.config.reassign_function {
+            Some(_) => self.runner_up(request_id).await,
+            None => None,
+        };
+
+        // a submission received before the deadline fired, possibly before a restart, already
+        // moved the request out of `Assigned`
+        let (winner, runner_up) = match runner_up {
+            Some(runner_up) => runner_up,
+            None => {
+                if !self
+                    .leave_assigned(request_id, RequestStatus::Failed, "delivery deadline missed")
+                    .await
+                {
+                    self.record(request_id, "delivered").await;
+                    return;
+                }
+                println!("Request {}: Winner missed the delivery deadline", request_id);
+                self.fail(request_id, "pending").await;
+                return;
+            }
+        };
+
+        let detail = format!("delivery deadline missed by {}", winner);
+        if !self
+            .leave_assigned(request_id, RequestStatus::Assigned(runner_up.to_owned()), &detail)
+            .await
+        {
+            self.record(request_id, "delivered").await;
+            return;
+        }
+        println!(
+            "Request {}: {} missed the delivery deadline, reassigning to {}",
+            request_id, winner, runner_up
+        );
+
+        // a payload sealed to the previous winner cannot be read by the runner-up, the requester seals it again
+        let res = sqlx::query(
+            "WITH missed AS (INSERT into delivery_misses (request_id, winner) values ($1, $2) ON CONFLICT DO NOTHING), \
+             resealed AS (DELETE from sealed_payloads where request_id=$1) \
+             UPDATE delivery_deadlines SET state='reassign_pending', winner=$3, attempts=0, detail=NULL, updated_at=now() \
+             where request_id=$1 and state='pending';",
+        )
+        .bind(request_id as i64)
+        .bind(&winner)
+        .bind(&runner_up)
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = res {
+            println!("Request {}: failed to record reassignment: {}", request_id, e);
+        }
+
+        self.send_reassign(request_id).await;
+    }
+
+    /// Current winner of `request_id` and the lowest bidder, earliest first, that has not missed
+    /// a deadline for it yet.
+    async fn runner_up(&self, request_id: u64) -> Option<(String, String)> {
+        let res: Result<Option<(String, String)>, sqlx::Error> = sqlx::query_as(
+            "SELECT d.winner, b.bidder from delivery_deadlines d join bids b on b.request_id = d.request_id \
+             where d.request_id=$1 and b.bidder <> d.winner \
+             and b.bidder not in (SELECT winner from delivery_misses where request_id=$1) \
+             order by b.price, b.created_at, b.id limit 1;",
+        )
+        .bind(request_id as i64)
+        .fetch_optional(&self.pool)
+        .await;
+
+        res.unwrap_or_else(|e| {
+            println!("Request {}: failed to read the runner-up: {}", request_id, e);
+            None
+        })
+    }
+
+    // moves the request out of `Assigned`, returns false when it was submitted meanwhile.
+    // The deadline stays armed until the database answers
+    async fn leave_assigned(&self, request_id: u64, to: RequestStatus, detail: &str) -> bool {
+        let mut delay = RETRY_DELAY;
+        loop {
+            match self
+                .tracker
+                .leave_assigned(request_id, to.to_owned(), Some(detail))
+                .await
+            {
+                Ok(left) => return left,
+                Err(e) => {
+                    println!(
+                        "Request {}: failed to record missed deadline, retrying in {:?}: {}",
+                        request_id, delay, e
+                    );
+                    sleep(delay).await;
+                    delay = (delay * 2).min(MAX_RETRY_DELAY);
+                }
+            }
+        }
+    }
+
+    // the request failed, refunds the requester if the contract allows it
+    async fn fail(&self, request_id: u64, from: &str) {
+        match &self.config.timeout_function {
+            Some(_) => {
+                self.set_state(request_id, from, "timeout_pending").await;
+                self.send_timeout(request_id).await;
+            }
+            None => self.set_state(request_id, from, "missed").await,
+        }
+    }
+
+    /// Sends the delivery timeout of `request_id` until it is executed. The deadline stays
+    /// `timeout_pending` meanwhile, so that the next boot sends it again if every attempt fails.
+    async fn send_timeout(&self, request_id: u64) {
+        let function = match &self.config.timeout_function {
+            Some(function) => function.to_owned(),
+            None => {
+                println!(
+                    "Request {}: DELIVERY_TIMEOUT_FUNCTION not set, delivery timeout not sent",
+                    request_id
+                );
+                return;
+            }
+        };
+
+        let call = MissedDeadlineCall::Timeout(function);
+        if self.send_until_executed(request_id, "timeout_pending", &call).await {
+            println!("Request {}: Delivery timeout executed", request_id);
+            self.set_state(request_id, "timeout_pending", "timed_out").await;
+        }
+    }
+
+    /// Hands `request_id` to the winner recorded on its deadline, then gives it a whole deadline.
+    /// Fails the request when the contract does not reassign it.
+    async fn send_reassign(&self, request_id: u64) {
+        let row: Result<Option<(String,)>, sqlx::Error> =
+            sqlx::query_as("SELECT winner from delivery_deadlines where request_id=$1;")
+                .bind(request_id as i64)
+                .fetch_optional(&self.pool)
+                .await;
+        let runner_up = match row {
+            Ok(Some((winner,))) => winner,
+            Ok(None) => return,
+            Err(e) => {
+                // left `reassign_pending`, the next boot sends it again
+                println!("Request {}: failed to read the runner-up: {}", request_id, e);
+                return;
+            }
+        };
+
+        let call = match (&self.config.reassign_function, AccountAddress::from_str(&runner_up)) {
+            (Some(function), Ok(address)) => MissedDeadlineCall::Reassign(function.to_owned(), address),
+            _ => {
+                println!("Request {}: cannot reassign to {}", request_id, runner_up);
+                return self.fail_reassignment(request_id).await;
+            }
+        };
+        if !self.send_until_executed(request_id, "reassign_pending", &call).await {
+            return self.fail_reassignment(request_id).await;
+        }
+
+        let deadline = match self.deadline_for(request_id).await {
+            Ok(deadline) => deadline,
+            Err(e) => {
+                println!("Request {}: failed to read the task type: {}", request_id, e);
+                self.config.for_task_type(None)
+            }
+        };
+        let due_at_micros = now_micros() + deadline.as_micros() as u64;
+        let res = sqlx::query(
+            "UPDATE delivery_deadlines SET state='pending', due_at=to_timestamp($2 / 1000000.0), updated_at=now() \
+             where request_id=$1 and state='reassign_pending';",
+        )
+        .bind(request_id as i64)
+        .bind(due_at_micros as i64)
+        .execute(&self.pool)
+        .await;
+        if let Err(e) = res {
+            println!("Request {}: failed to record reassignment: {}", request_id, e);
+        }
+
+        println!(
+            "Request {}: Reassigned, {} has {:?} to deliver",
+            request_id, runner_up, deadline
+        );
+        self.arm(request_id, due_at_micros);
+    }
+
+    // the contract kept the previous winner, the request fails as if nobody could take it
+    async fn fail_reassignment(&self, request_id: u64) {
+        if !self
+            .leave_assigned(request_id, RequestStatus::Failed, "reassignment failed")
+            .await
+        {
+            self.set_state(request_id, "reassign_pending", "delivered").await;
+            return;
+        }
+        self.fail(request_id, "reassign_pending").await;
+    }
+
+    // sends `call` until it is executed, returns false once it failed `MAX_SEND_TRIES` times
+    async fn send_until_executed(
+        &self,
+        request_id: u64,
+        state: &str,
+        call: &MissedDeadlineCall,
+    ) -> bool {
+        let mut delay = RETRY_DELAY;
+        for attempt in 1..=MAX_SEND_TRIES {
+            let failure = match self.try_send(request_id, call).await {
+                Ok(()) => return true,
+                Err(failure) => failure,
+            };
+
+            println!(
+                "Request {}: {} failed (attempt {}): {}",
+                request_id, state, attempt, failure
+            );
+            self.record_attempt(request_id, state, &failure).await;
+            // the sequence number may be stale, the next transaction uses the current one
+            if let Ok(res) = self.rest_client.get_account(self.account.address()).await {
+                self.account.set_sequence_number(res.inner().sequence_number);
+            }
+            if attempt < MAX_SEND_TRIES {
+                sleep(delay).await;
+                delay = (delay * 2).min(MAX_RETRY_DELAY);
+            }
+        }
+        false
+    }
+
+    // sends the transaction and waits for it to be executed
+    async fn try_send(&self, request_id: u64, call: &MissedDeadlineCall) -> Result<(), String> {
+        let pending = match call {
+            MissedDeadlineCall::Timeout(function) => {
+                call_request_function(
+                    function.to_owned(),
+                    request_id,
+                    &self.account,
+                    &self.rest_client,
+                )
+                .await
+            }
+            MissedDeadlineCall::Reassign(function, runner_up) => {
+                call_request_account_function(
+                    function.to_owned(),
+                    request_id,
+                    *runner_up,
+                    &self.account,
+                    &self.rest_client,
+                )
+                .await
+            }
+        }
+        .map_err(|e| e.to_string())?;
+        let inner = pending.inner();
+        println!("Request {}: Sent {}", request_id, inner.hash);
+
+        let executed = self
+            .rest_client
+            .wait_for_transaction_by_hash(
+                inner.hash.into(),
+                inner.request.expiration_timestamp_secs.into(),
+                None,
+                None,
+            )
+            .await
+            .map_err(|e| e.to_string())?;
+        match executed.inner() {
+            Transaction::UserTransaction(user_tx) if user_tx.info.success => Ok(()),
+            Transaction::UserTransaction(user_tx) => Err(user_tx.info.vm_status.to_owned()),
+            _ => Err("not a user transaction".to_owned()),
+        }
+    }
+
+    async fn record_attempt(&self, request_id: u64, state: &str, detail: &str) {
+        let res = sqlx::query(
+            "UPDATE delivery_deadlines SET attempts=attempts + 1, detail=$3, updated_at=now() \
+             where request_id=$1 and state=$2;",
+        )
+        .bind(request_id as i64)
+        .bind(state)
+        .bind(detail)
+        .execute(&self.pool)
+        .await;
+
+        if let Err(e) = res {
+            println!("Request {}: failed to record attempt: {}", request_id, e);
+        }
+    }
+}
@@ -0,0 +1,377 @@
+use std::fmt;
+
+use chain_listener::events::EventEnvelope;
+use proxirun_sdk::events::{ContractEvent, OnNewWorkRequestBid};
+use serde::Serialize;
+use sqlx::{Pool, Postgres, Transaction};
+use tokio::sync::broadcast;
+
+// updates kept for slow subscribers before they start skipping
+const UPDATES_CAPACITY: usize = 256;
+
+/// Stage of a request, from its creation on chain to the commit of its result.
+/// `Failed` and `Expired` can be reached from any stage that is not final.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RequestStatus {
+    Created,
+    Bidding,
+    Finalizing,
+    Assigned(String),
+    Submitted,
+    Committed,
+    Failed,
+    /// The auction closed without any bid
+    Expired,
+}
+
+impl RequestStatus {
+    pub fn name(&self) -> &'static str {
+        match self {
+            RequestStatus::Created => "Created",
+            RequestStatus::Bidding => "Bidding",
+            RequestStatus::Finalizing => "Finalizing",
+            RequestStatus::Assigned(_) => "Assigned",
+            RequestStatus::Submitted => "Submitted",
+            RequestStatus::Committed => "Committed",
+            RequestStatus::Failed => "Failed",
+            RequestStatus::Expired => "Expired",
+        }
+    }
+
+    /// Rebuilds the status from its `request_status` row
+    pub fn from_row(status: &str, winner: Option<String>) -> Option<Self> {
+        match status {
+            "Created" => Some(RequestStatus::Created),
+            "Bidding" => Some(RequestStatus::Bidding),
+            "Finalizing" => Some(RequestStatus::Finalizing),
+            "Assigned" => Some(RequestStatus::Assigned(winner.unwrap_or_default())),
+            "Submitted" => Some(RequestStatus::Submitted),
+            "Committed" => Some(RequestStatus::Committed),
+            "Failed" => Some(RequestStatus::Failed),
+            "Expired" => Some(RequestStatus::Expired),
+            _ => None,
+        }
+    }
+
+    pub fn winner(&self) -> Option<&str> {
+        match self {
+            RequestStatus::Assigned(winner) => Some(winner),
+            _ => None,
+        }
+    }
+
+    pub fn is_final(&self) -> bool {
+        matches!(
+            self,
+            RequestStatus::Committed | RequestStatus::Failed | RequestStatus::Expired
+        )
+    }
+
+    // position along the happy path
+    fn rank(&self) -> u8 {
+        match self {
+            RequestStatus::Created => 0,
+            RequestStatus::Bidding => 1,
+            RequestStatus::Finalizing => 2,
+            RequestStatus::Assigned(_) => 3,
+            RequestStatus::Submitted => 4,
+            RequestStatus::Committed => 5,
+            RequestStatus::Failed | RequestStatus::Expired => 6,
+        }
+    }
+
+    /// Requests only move forward, so that late or replayed events cannot roll them back.
+    pub fn can_transition_to(&self, to: &RequestStatus) -> bool {
+        !self.is_final() && to.rank() > self.rank()
+    }
+}
+
+impl fmt::Display for RequestStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            RequestStatus::Assigned(winner) => write!(f, "Assigned({})", winner),
+            status => write!(f, "{}", status.name()),
+        }
+    }
+}
+
+/// A transition of a request, as pushed to the subscribers.
+#[derive(Debug, Clone, Serialize)]
+pub struct StatusUpdate {
+    pub request_id: u64,
+    pub from: Option<&'static str>,
+    pub status: &'static str,
+    pub winner: Option<String>,
+    pub detail: Option<String>,
+    /// Unix time of the transition, in milliseconds
+    pub at_ms: i64,
+}
+
+/// Persists the transitions of the requests and broadcasts them as they happen.
+#[derive(Clone)]
+pub struct RequestTracker {
+    pool: Pool<Postgres>,
+    updates: broadcast::Sender<StatusUpdate>,
+}
+
+impl RequestTracker {
+    pub fn new(pool: Pool<Postgres>) -> Self {
+        let (updates, _) = broadcast::channel(UPDATES_CAPACITY);
+        RequestTracker { pool, updates }
+    }
+
+    /// Receives the transitions of every request made from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<StatusUpdate> {
+        self.updates.subscribe()
+    }
+
+    /// Moves the request to `to` and records the transition, returns false when the
+    /// transition is not allowed from the current status.
+    /// Requests unknown to the database, e.g. created before the orchestrator started, are created in `to`.
+    pub async fn transition(
+        &self,
+        request_id: u64,
+        to: RequestStatus,
+        detail: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        self.transition_at(request_id, to, detail, None).await
+    }
+
+    /// `transition` dated at `at_us` (Unix time in microseconds) instead of now, for the
+    /// transitions caused by contract events, which may be received long after they were emitted.
+    async fn transition_at(
+        &self,
+        request_id: u64,
+        to: RequestStatus,
+        detail: Option<&str>,
+        at_us: Option<u64>,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let current: Option<(String, Option<String>)> = sqlx::query_as(
+            "SELECT status, winner from request_status where request_id=$1 FOR UPDATE;",
+        )
+        .bind(request_id as i64)
+        .fetch_optional(&mut *tx)
+        .await?;
+        let from = current.and_then(|(status, winner)| RequestStatus::from_row(&status, winner));
+
+        if let Some(from) = &from {
+            if !from.can_transition_to(&to) {
+                return Ok(false);
+            }
+        }
+
+        sqlx::query(
+            "INSERT into request_status (request_id, status, winner, updated_at) values ($1, $2, $3, now()) \
+             ON CONFLICT (request_id) DO UPDATE SET status=$2, winner=COALESCE($3, request_status.winner), updated_at=now();",
+        )
+        .bind(request_id as i64)
+        .bind(to.name())
+        .bind(to.winner())
+        .execute(&mut *tx)
+        .await?;
+
+        self.record_transition(
+            tx,
+            request_id,
+            from.as_ref().map(|status| status.name()),
+            &to,
+            detail,
+            at_us,
+        )
+        .await?;
+
+        Ok(true)
+    }
+
+    /// Moves an `Assigned` request to `to` with a single conditional update, returns false when
+    /// it is not `Assigned` anymore. A submission and the expiry of the delivery deadline can
+    /// race for the same request, only one of them moves it. `to` may be `Assigned` to another
+    /// winner, when the request is handed to the runner-up.
+    pub async fn leave_assigned(
+        &self,
+        request_id: u64,
+        to: RequestStatus,
+        detail: Option<&str>,
+    ) -> Result<bool, sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+
+        let res = sqlx::query(
+            "UPDATE request_status SET status=$2, winner=COALESCE($3, winner), updated_at=now() \
+             where request_id=$1 and status='Assigned';",
+        )
+        .bind(request_id as i64)
+        .bind(to.name())
+        .bind(to.winner())
+        .execute(&mut *tx)
+        .await?;
+        if res.rows_affected() == 0 {
+            return Ok(false);
+        }
+
+        self.record_transition(tx, request_id, Some("Assigned"), &to, detail, None)
+            .await?;
+
+        Ok(true)
+    }
+
+    // logs the transition, commits it and broadcasts it
+    async fn record_transition(
+        &self,
+        mut tx: Transaction<'_, Postgres>,
+        request_id: u64,
+        from: Option<&'static str>,
+        to: &RequestStatus,
+        detail: Option<&str>,
+        at_us: Option<u64>,
+    ) -> Result<(), sqlx::Error> {
+        let (at_ms,): (i64,) = sqlx::query_as(
+            "INSERT into request_transitions (request_id, from_status, to_status, detail, created_at) \
+             values ($1, $2, $3, $4, COALESCE(to_timestamp($5 / 1000000.0), now())) \
+             RETURNING (extract(epoch from created_at) * 1000)::BIGINT;",
+        )
+        .bind(request_id as i64)
+        .bind(from)
+        .bind(to.name())
+        .bind(detail)
+        .bind(at_us.map(|at_us| at_us as i64))
+        .fetch_one(&mut *tx)
+        .await?;
+
+        tx.commit().await?;
+
+        println!("Request {}: {}", request_id, to);
+
+        // no subscriber is not an error
+        let _ = self.updates.send(StatusUpdate {
+            request_id,
+            from,
+            status: to.name(),
+            winner: to.winner().map(|w| w.to_owned()),
+            detail: detail.map(|d| d.to_owned()),
+            at_ms,
+        });
+
+        Ok(())
+    }
+
+    /// `transition` for the callers that only log failures, tracking never stops the orchestrator.
+    pub async fn track(&self, request_id: u64, to: RequestStatus, detail: Option<&str>) {
+        self.track_at(request_id, to, detail, None).await
+    }
+
+    async fn track_at(
+        &self,
+        request_id: u64,
+        to: RequestStatus,
+        detail: Option<&str>,
+        at_us: Option<u64>,
+    ) {
+        let name = to.name();
+        match self.transition_at(request_id, to, detail, at_us).await {
+            Ok(true) => (),
+            Ok(false) => println!("Request {}: ignoring transition to {}", request_id, name),
+            Err(e) => println!(
+                "Request {}: failed to record transition to {}: {}",
+                request_id, name, e
+            ),
+        }
+    }
+
+    // bids are dated with the chain timestamp, replayed and caught up events keep their time
+    async fn record_bid(&self, bid: &OnNewWorkRequestBid, at_us: u64) -> Result<(), sqlx::Error> {
+        sqlx::query(
+            "INSERT into bids (request_id, bidder, price, created_at) values ($1, $2, $3, to_timestamp($4 / 1000000.0)) \
+             ON CONFLICT DO NOTHING;",
+        )
+        .bind(bid.request_id as i64)
+        .bind(&bid.bidder)
+        .bind(bid.price as i64)
+        .bind(at_us as i64)
+        .execute(&self.pool)
+        .await?;
+
+        Ok(())
+    }
+
+    async fn has_bids(&self, request_id: u64) -> Result<bool, sqlx::Error> {
+        let (count,): (i64,) = sqlx::query_as("SELECT count(*) from bids where request_id=$1;")
+            .bind(request_id as i64)
+            .fetch_one(&self.pool)
+            .await?;
+
+        Ok(count > 0)
+    }
+
+    /// Current status of the request, `None` if it is unknown
+    pub async fn status(&self, request_id: u64) -> Result<Option<RequestStatus>, sqlx::Error> {
+        let row: Option<(String, Option<String>)> =
+            sqlx::query_as("SELECT status, winner from request_status where request_id=$1;")
+                .bind(request_id as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.and_then(|(status, winner)| RequestStatus::from_row(&status, winner)))
+    }
+
+    /// Winner recorded from `OnBidWon`, if the auction is over
+    pub async fn winner(&self, request_id: u64) -> Result<Option<String>, sqlx::Error> {
+        let row: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT winner from request_status where request_id=$1;")
+                .bind(request_id as i64)
+                .fetch_optional(&self.pool)
+                .await?;
+
+        Ok(row.and_then(|(winner,)| winner))
+    }
+
+    /// Applies the effect of a contract event on the request it refers to, dating it with
+    /// the timestamp of the transaction that emitted it.
+    pub async fn handle_event(&self, envelope: &EventEnvelope) {
+        let at_us = Some(envelope.timestamp_us);
+        match &envelope.event {
+            ContractEvent::OnNewWorkRequest(e) => {
+                self.track_at(e.request_id, RequestStatus::Created, None, at_us)
+                    .await
+            }
+            ContractEvent::OnNewWorkRequestBid(e) => {
+                if let Err(err) = self.record_bid(e, envelope.timestamp_us).await {
+                    println!("Request {}: failed to record bid: {}", e.request_id, err);
+                }
+                // only the first bid moves the request, the next ones are not a transition
+                if let Err(err) = self
+                    .transition_at(e.request_id, RequestStatus::Bidding, None, at_us)
+                    .await
+                {
+                    println!(
+                        "Request {}: failed to record transition to Bidding: {}",
+                        e.request_id, err
+                    );
+                }
+            }
+            ContractEvent::OnBidWon(e) => {
+                self.track_at(
+                    e.request_id,
+                    RequestStatus::Assigned(e.winner.to_owned()),
+                    None,
+                    at_us,
+                )
+                .await
+            }
+            ContractEvent::OnAuctionFailure(e) => {
+                let status = match self.has_bids(e.request_id).await {
+                    Ok(false) => RequestStatus::Expired,
+                    _ => RequestStatus::Failed,
+                };
+                self.track_at(e.request_id, status, Some("auction failed"), at_us)
+                    .await
+            }
+            ContractEvent::OnWorkRequestCompleted(e) => {
+                self.track_at(e.request_id, RequestStatus::Committed, None, at_us)
+                    .await
+            }
+            ContractEvent::Unknown(_) => (),
+        }
+    }
+}
@@ -1,19 +1,42 @@
-use std::fs;
+mod auth;
+mod cursor;
+mod db;
+mod deadlines;
+mod lifecycle;
+mod market;
+mod media;
+mod outputs;
+mod receipts;
+mod scheduler;
+mod sealed;
+mod status;
+mod storage;
+mod transcode;
+mod variants;
+
 use std::sync::Arc;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 
-use actix_files::NamedFile;
 use actix_multipart::Multipart;
+use actix_web::http::header::{HeaderName, HeaderValue};
 use actix_web::http::StatusCode;
 use actix_web::{get, post, web, App, HttpRequest, HttpResponse, HttpServer, Responder};
-use aptos_sdk::rest_client::Transaction;
-use aptos_sdk::{rest_client::Client, types::LocalAccount};
-use chain_listener::events_listener::run_listener;
+use aptos_sdk::rest_client::{Client, Transaction};
+use aptos_sdk::types::LocalAccount;
+use chain_listener::config::ListenerConfig;
+use chain_listener::event_source::{
+    EventSource, GrpcEventSource, RecordingEventSource, ReplayEventSource, RestEventSource,
+};
+use chain_listener::events::{DecodeError, EventEnvelope};
+use chain_listener::events_listener::run_envelope_listener_with_source;
+use chain_listener::metrics::{render_prometheus, PROMETHEUS_CONTENT_TYPE};
+use chain_listener::stats::ListenerStats;
 use proxirun_sdk::constants::CONTRACT_MODULE;
 use proxirun_sdk::contract_interact::commit;
 use proxirun_sdk::orchestrator::{ImageGenerationSettings, VoiceGenerationSettings};
+use proxirun_sdk::receipt::{sign_receipt, CONTENT_HASH_HEADER};
+use proxirun_sdk::submission::content_hash;
 use proxirun_sdk::{
-    contract_interact::finalize_auction,
     events::ContractEvent,
     orchestrator::{TaskDefinition, TaskPayload, TextGenerationPayload, TextGenerationSettings},
 };
@@ -23,15 +46,30 @@ use dotenv::dotenv;
 use serde::{Deserialize, Serialize};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::{Pool, Postgres};
-use tokio::io::AsyncWriteExt;
-use tokio::time::{sleep, sleep_until, Instant};
+use tokio::time::sleep;
 use tokio_stream::StreamExt;
 
+use auth::{verify_payload_reader, verify_submitter, AuthKeyCache, ChallengeStore, Submission};
+use cursor::{load_cursor, save_cursor, Cursor};
+use db::run_migrations;
+use deadlines::{DeadlineConfig, DeliveryDeadlines};
+use lifecycle::{RequestStatus, RequestTracker};
+use media::{validate, MediaError, MediaKind, MediaLimits};
+use outputs::{etag, not_modified, serve_output, store_output};
+use receipts::{fetch_receipt, store_receipt};
+use scheduler::FinalizationScheduler;
+use sealed::fetch_sealed_payload;
+use storage::ResultStore;
+use variants::{pregenerate_variants, RenderLocks, VariantQuery};
+
 const INDEXER_URL: &'static str = "https://grpc.testnet.aptoslabs.com";
 const TESTNET_NODE: &'static str = "https://fullnode.testnet.aptoslabs.com";
 
 const DELTA_TIME: u64 = 500000; // 500 ms
 
+const EVENT_QUEUE_SIZE: usize = 1024;
+const LISTENER_REPORT_INTERVAL: Duration = Duration::from_secs(60);
+
 #[derive(sqlx::FromRow)]
 struct RequestDataDb {
     pub request_id: i64,
@@ -39,6 +77,7 @@ struct RequestDataDb {
     pub data: String,
     pub model: String,
     pub requester: String,
+    pub encrypted: bool,
 }
 
 #[derive(sqlx::FromRow, Serialize)]
@@ -52,6 +91,114 @@ pub struct AppState {
     pub wallet: Arc<LocalAccount>,
     pub rest_client: Arc<Client>, // Mutex for concurrent access
     pub db_pool: Pool<Postgres>,
+    pub listener_stats: ListenerStats,
+    pub tracker: RequestTracker,
+    pub challenges: ChallengeStore,
+    pub auth_keys: AuthKeyCache,
+    pub store: Arc<dyn ResultStore>,
+    pub media_limits: MediaLimits,
+    pub deadlines: DeliveryDeadlines,
+    pub render_locks: RenderLocks,
+    /// Events are replayed from a file, submissions are not committed on chain
+    pub replaying: bool,
+}
+
+#[get("/metrics")]
+async fn metrics(app_state: web::Data<AppState>) -> impl Responder {
+    HttpResponse::Ok()
+        .content_type(PROMETHEUS_CONTENT_TYPE)
+        .body(render_prometheus(&app_state.listener_stats))
+}
+
+/// Moves the request out of `Assigned` before its result is stored, so that the result of a
+/// request failed by its delivery deadline is never stored nor served.
+async fn claim_submission(
+    id: u64,
+    submission: Submission,
+    app_state: &AppState,
+) -> Result<(), actix_web::Error> {
+    if submission != Submission::New {
+        return Ok(());
+    }
+
+    // the delivery deadline may have expired the request since it was verified
+    match app_state.tracker.leave_assigned(id, RequestStatus::Submitted, None).await {
+        Ok(true) => (),
+        Ok(false) => {
+            return Err(actix_web::error::ErrorConflict("The request is no longer assigned"))
+        }
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+    app_state.deadlines.delivered(id).await;
+    Ok(())
+}
+
+/// Commits the saved submission on chain and signs a receipt of the SHA-256 of `content`.
+/// The request is committed once the contract emits `OnWorkRequestCompleted`.
+/// When the commit fails the request stays submitted, and the winner can send the same content again.
+async fn commit_submission(
+    id: u64,
+    content: &[u8],
+    app_state: &AppState,
+) -> Result<(), actix_web::Error> {
+    if app_state.replaying {
+        println!("Request {}: Replay mode, commit not sent", id);
+        return Ok(());
+    }
+
+    // update on smart contract
+    let pending = match commit(id, &app_state.wallet, &app_state.rest_client).await {
+        Ok(pending) => pending,
+        Err(e) => return Err(commit_failed(id, e.to_string(), app_state).await),
+    };
+
+    // the receipt is only signed once the commit is executed, until then the winner can retry
+    let inner = pending.inner();
+    let executed = app_state
+        .rest_client
+        .wait_for_transaction_by_hash(
+            inner.hash.into(),
+            inner.request.expiration_timestamp_secs.into(),
+            None,
+            None,
+        )
+        .await;
+    match executed {
+        Ok(txn) => match txn.inner() {
+            Transaction::UserTransaction(user_tx) if user_tx.info.success => (),
+            Transaction::UserTransaction(user_tx) => {
+                return Err(commit_failed(id, user_tx.info.vm_status.to_owned(), app_state).await)
+            }
+            _ => {
+                return Err(commit_failed(id, "not a user transaction".to_owned(), app_state).await)
+            }
+        },
+        Err(e) => return Err(commit_failed(id, e.to_string(), app_state).await),
+    }
+
+    println!("Request {}: Received commit", id);
+
+    // the commit entry function only takes the request id, the receipt binds the output to it
+    let receipt = sign_receipt(
+        &app_state.wallet,
+        id,
+        &content_hash(content),
+        &inner.hash.to_string(),
+    );
+    if let Err(e) = store_receipt(&app_state.db_pool, &receipt).await {
+        println!("Request {}: failed to store receipt: {}", id, e);
+    }
+    Ok(())
+}
+
+// the request stays `Submitted` without a receipt, so that the winner can submit the same content again
+async fn commit_failed(id: u64, reason: String, app_state: &AppState) -> actix_web::Error {
+    println!("Request {}: commit failed: {}", id, reason);
+    // tx failed, possibly due to invalid sequence number, shared with the finalizations and the delivery timeouts
+    if let Ok(res) = app_state.rest_client.get_account(app_state.wallet.address()).await {
+        app_state.wallet.set_sequence_number(res.inner().sequence_number);
+    }
+    actix_web::error::ErrorServiceUnavailable(format!("commit failed: {}", reason))
 }
 
 #[get("/request-details/{id}")]
@@ -112,8 +259,36 @@ async fn request_details(id: web::Path<u64>, app_state: web::Data<AppState>) ->
     }
 }
 
+/// Nonce to sign with the winner's key to read the payload of the request, once the auction has a winner.
+#[get("/request-payload/{id}/challenge")]
+async fn request_payload_challenge(
+    id: web::Path<u64>,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    match app_state.tracker.winner(*id).await {
+        Ok(Some(_)) => (),
+        Ok(None) => return Err(actix_web::error::ErrorConflict("The auction has no winner yet")),
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+
+    match app_state.challenges.issue(*id) {
+        Some(challenge) => Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().json(challenge)),
+        None => Err(actix_web::error::ErrorTooManyRequests(
+            "Too many outstanding challenges for this request",
+        )),
+    }
+}
+
+/// The prompts are private to the requester and the winner of the auction,
+/// the caller must answer a challenge of `/request-payload/{id}/challenge`.
 #[get("/request-payload/{id}")]
-async fn request_payload(id: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+async fn request_payload(
+    id: web::Path<u64>,
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    verify_payload_reader(&app_state, *id, &req).await?;
+
     let mut data = None;
     let mut try_id: usize = 0;
     let max_retries: usize = 5;
@@ -146,6 +321,20 @@ async fn request_payload(id: web::Path<u64>, app_state: web::Data<AppState>) ->
         }
     };
 
+    // a sealed payload is relayed as is, only the winner can decrypt it
+    match fetch_sealed_payload(&app_state.db_pool, *id).await {
+        Ok(Some(sealed)) => {
+            return Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().json(&sealed));
+        }
+        Ok(None) if data.encrypted => {
+            return Err(actix_web::error::ErrorConflict(
+                "The requester has not uploaded the sealed payload yet",
+            ));
+        }
+        Ok(None) => (),
+        Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+    }
+
     match data.task_type.as_str() {
         "Text Generation" => {
             return Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().json(
@@ -171,26 +360,103 @@ async fn request_payload(id: web::Path<u64>, app_state: web::Data<AppState>) ->
     }
 }
 
+/// Adds the hash of the delivered output, so that the requester can check it against the receipt.
+async fn with_content_hash(mut response: HttpResponse, app_state: &AppState, id: u64) -> HttpResponse {
+    if let Ok(Some(receipt)) = fetch_receipt(&app_state.db_pool, id).await {
+        if let Ok(value) = HeaderValue::from_str(&receipt.content_hash) {
+            let name = HeaderName::from_bytes(CONTENT_HASH_HEADER.as_bytes()).unwrap();
+            response.headers_mut().insert(name, value);
+        }
+    }
+    response
+}
+
+/// Reads the content of the `file` field, the submission is only stored once its signature is checked.
+/// Other fields are rejected, so that a misnamed field is not taken for an empty submission.
+async fn read_file_field(mut payload: Multipart, max_size: usize) -> Result<Vec<u8>, actix_web::Error> {
+    let mut content = None;
+    while let Some(field) = payload.next().await {
+        let mut field = match field {
+            Ok(field) => field,
+            Err(e) => return Err(actix_web::error::ErrorBadRequest(e.to_string())),
+        };
+
+        match field.name() {
+            Some("file") if content.is_none() => (),
+            Some("file") => return Err(actix_web::error::ErrorBadRequest("More than one file field")),
+            name => {
+                return Err(actix_web::error::ErrorBadRequest(format!(
+                    "Unexpected field {:?}, the submission goes in the file field",
+                    name.unwrap_or_default()
+                )))
+            }
+        }
+
+        let mut file = vec![];
+        while let Some(chunk) = field.next().await {
+            let chunk = match chunk {
+                Ok(chunk) => chunk,
+                Err(e) => return Err(actix_web::error::ErrorBadRequest(e.to_string())),
+            };
+
+            // stop reading as soon as the limit is crossed
+            if file.len() + chunk.len() > max_size {
+                return Err(MediaError::TooLarge { max_size }.into());
+            }
+            file.extend_from_slice(&chunk);
+        }
+        content = Some(file);
+    }
+
+    content.ok_or(actix_web::error::ErrorBadRequest("Missing file field"))
+}
+
+/// Sniffs and decodes the submitted file on the blocking pool, returns it with its MIME type.
+async fn validate_upload(
+    id: u64,
+    content: Vec<u8>,
+    kind: MediaKind,
+    limits: MediaLimits,
+) -> Result<(Vec<u8>, &'static str), actix_web::Error> {
+    let validated =
+        web::block(move || validate(&content, kind, &limits).map(|mime| (content, mime))).await?;
+
+    validated.map_err(|e| {
+        println!("Request {}: rejected submission: {}", id, e);
+        e.into()
+    })
+}
+
 #[post("/submit-text/{id}")]
 async fn submit_text(
     id: web::Path<u64>,
     payload: String,
     app_state: web::Data<AppState>,
+    req: HttpRequest,
 ) -> impl Responder {
-    // save on db
-    sqlx::query("INSERT into text_completions (request_id, content) values ($1, $2) ;")
-        .bind(*id as i64)
-        .bind(payload)
-        .execute(&app_state.db_pool)
-        .await
-        .unwrap();
+    let submission = verify_submitter(&app_state, *id, payload.as_bytes(), &req).await?;
+    claim_submission(*id, submission, &app_state).await?;
 
-    // update on smart contract
-    commit(*id, &app_state.wallet, &app_state.rest_client)
-        .await
-        .unwrap();
+    // save on db
+    if submission.needs_storing() {
+        let res = sqlx::query("INSERT into text_completions (request_id, content) values ($1, $2) ;")
+            .bind(*id as i64)
+            .bind(&payload)
+            .execute(&app_state.db_pool)
+            .await;
+        match res {
+            Ok(_) => (),
+            Err(sqlx::Error::Database(e)) if e.is_unique_violation() => {
+                println!("Request {}: rejected a second completion", *id);
+                return Err(actix_web::error::ErrorConflict(
+                    "A completion was already submitted for this request",
+                ));
+            }
+            Err(e) => return Err(actix_web::error::ErrorInternalServerError(e)),
+        }
+    }
 
-    println!("Request {}: Received commit", *id);
+    commit_submission(*id, payload.as_bytes(), &app_state).await?;
 
     Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().body("Submission saved successfully"))
 }
@@ -198,75 +464,54 @@ async fn submit_text(
 #[post("/submit-image/{id}")]
 async fn submit_image(
     id: web::Path<u64>,
-    mut payload: Multipart,
+    payload: Multipart,
     app_state: web::Data<AppState>, // Access shared state
+    req: HttpRequest,
 ) -> impl Responder {
-    let file_path = format!("./uploads/{}.jpg", id);
-    let mut file = tokio::fs::File::create(file_path).await?;
-
-    while let Some(field) = payload.next().await {
-        let mut field = match field {
-            Ok(field) => field,
-            Err(e) => return Err(actix_web::error::ErrorBadRequest(e.to_string())),
-        };
+    let content = read_file_field(payload, app_state.media_limits.max_image_size).await?;
+    let submission = verify_submitter(&app_state, *id, &content, &req).await?;
+    let (content, content_type) =
+        validate_upload(*id, content, MediaKind::Image, app_state.media_limits).await?;
+    claim_submission(*id, submission, &app_state).await?;
+
+    let hash = if submission.needs_storing() {
+        Some(store_output(&app_state, *id, content_type, &content).await?)
+    } else {
+        None
+    };
 
-        if field.name() == Some("file") {
-            // Write the file content to the file
-            while let Some(chunk) = field.next().await {
-                let chunk = match chunk {
-                    Ok(chunk) => chunk,
-                    Err(e) => return Err(actix_web::error::ErrorBadRequest(e.to_string())),
-                };
+    commit_submission(*id, &content, &app_state).await?;
 
-                let _ = file.write_all(&chunk).await?;
-            }
-        }
+    // the variants of a retried submission were rendered the first time
+    if let Some(hash) = hash {
+        let variants_state = app_state.clone();
+        let request_id = *id;
+        tokio::spawn(async move {
+            pregenerate_variants(&variants_state, request_id, &hash, &content).await;
+        });
     }
 
-    // update on smart contract
-    commit(*id, &app_state.wallet, &app_state.rest_client)
-        .await
-        .unwrap();
-
-    println!("Request {}: Received commit", *id);
-
     Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().body("Submission saved successfully"))
 }
 
 #[post("/submit-voice/{id}")]
 async fn submit_voice(
     id: web::Path<u64>,
-    mut payload: Multipart,
+    payload: Multipart,
     app_state: web::Data<AppState>, // Access shared state
+    req: HttpRequest,
 ) -> impl Responder {
-    let file_path = format!("./uploads/{}.wav", id);
-    let mut file = tokio::fs::File::create(file_path).await?;
-
-    while let Some(field) = payload.next().await {
-        let mut field = match field {
-            Ok(field) => field,
-            Err(e) => return Err(actix_web::error::ErrorBadRequest(e.to_string())),
-        };
-
-        if field.name() == Some("file") {
-            // Write the file content to the file
-            while let Some(chunk) = field.next().await {
-                let chunk = match chunk {
-                    Ok(chunk) => chunk,
-                    Err(e) => return Err(actix_web::error::ErrorBadRequest(e.to_string())),
-                };
-
-                let _ = file.write_all(&chunk).await?;
-            }
-        }
+    let content = read_file_field(payload, app_state.media_limits.max_audio_size).await?;
+    let submission = verify_submitter(&app_state, *id, &content, &req).await?;
+    let (content, content_type) =
+        validate_upload(*id, content, MediaKind::Audio, app_state.media_limits).await?;
+    claim_submission(*id, submission, &app_state).await?;
+
+    if submission.needs_storing() {
+        store_output(&app_state, *id, content_type, &content).await?;
     }
 
-    // update on smart contract
-    commit(*id, &app_state.wallet, &app_state.rest_client)
-        .await
-        .unwrap();
-
-    println!("Request {}: Received commit", *id);
+    commit_submission(*id, &content, &app_state).await?;
 
     Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().body("Submission saved successfully"))
 }
@@ -274,6 +519,7 @@ async fn submit_voice(
 #[get("/output/{id}")]
 async fn get_output(
     id: web::Path<u64>,
+    query: web::Query<VariantQuery>,
     app_state: web::Data<AppState>,
     req: HttpRequest,
 ) -> impl Responder {
@@ -319,10 +565,16 @@ async fn get_output(
             .await
             {
                 Ok(val) => {
+                    let hash = hex::encode(content_hash(val.content.as_bytes()));
+                    if let Some(response) = not_modified(&req, &hash) {
+                        return Ok(response);
+                    }
+                    let response = HttpResponse::Ok()
+                        .content_type("application/json")
+                        .insert_header(etag(&hash))
+                        .json(val);
                     return Ok::<HttpResponse, actix_web::Error>(
-                        HttpResponse::Ok()
-                            .content_type("application/json")
-                            .json(val),
+                        with_content_hash(response, &app_state, *id).await,
                     );
                 }
                 Err(_) => {
@@ -332,15 +584,14 @@ async fn get_output(
                 }
             }
         }
-        "Image Generation" => {
+        "Image Generation" | "Voice Generation" => {
             // send file
-            let file_path = format!("./uploads/{}.jpg", *id);
-            return Ok(NamedFile::open(file_path).unwrap().into_response(&req));
-        }
-        "Voice Generation" => {
-            // send file
-            let file_path = format!("./uploads/{}.wav", *id);
-            return Ok(NamedFile::open(file_path).unwrap().into_response(&req));
+            let response = serve_output(&app_state, *id, &query, &req).await?;
+            // the receipt hash is the one of the submitted file, not of its variants
+            if !query.is_original() {
+                return Ok(response);
+            }
+            return Ok(with_content_hash(response, &app_state, *id).await);
         }
         _ => {
             println!("Unexpected");
@@ -355,7 +606,25 @@ async fn get_output(
 #[actix_web::main]
 async fn main() -> std::io::Result<()> {
     dotenv().ok();
-    let auth_token = std::env::var("INDEXER_AUTH_KEY").expect("INDEXER_AUTH_KEY must be set.");
+
+    // `orchestrator migrate` only applies the database migrations
+    if std::env::args().nth(1).as_deref() == Some("migrate") {
+        let db_url = std::env::var("DB_URL").expect("DB_URL must be set.");
+        let pool = PgPoolOptions::new()
+            .max_connections(1)
+            .connect(&db_url)
+            .await
+            .unwrap();
+        run_migrations(&pool)
+            .await
+            .expect("Failed to apply database migrations.");
+        println!("Database migrations applied");
+        return Ok(());
+    }
+
+    // without an indexer auth token, fall back to polling the fullnode
+    let auth_token = std::env::var("INDEXER_AUTH_KEY").ok();
+    let listener_config = ListenerConfig::from_env().expect("Invalid listener configuration.");
     let admin_priv_key =
         std::env::var("ADMIN_PRIVATE_KEY").expect("ADMIN_PRIVATE_KEY must be set.");
 
@@ -365,10 +634,16 @@ async fn main() -> std::io::Result<()> {
         std::env::var("ORCHESTRATOR_PORT").expect("ORCHESTRATOR_PORT must be set.");
     let db_url = std::env::var("DB_URL").expect("DB_URL must be set.");
 
+    // optional event stream recording and replay, to reproduce incidents offline
+    let record_file = std::env::var("RECORD_EVENTS_FILE").ok();
+    let replay_file = std::env::var("REPLAY_EVENTS_FILE").ok();
+    let replay_speed: f64 = std::env::var("REPLAY_SPEED")
+        .map(|speed| speed.parse().expect("REPLAY_SPEED must be a number."))
+        .unwrap_or(1.0);
+
     println!("Starting orchestrator on port: {}", orchestrator_port);
 
-    // make sure that the uploads folder exists
-    fs::create_dir_all("./uploads")?;
+    let store = storage::from_env().expect("Invalid storage configuration.");
 
     // connect to db
     let pool = PgPoolOptions::new()
@@ -376,116 +651,188 @@ async fn main() -> std::io::Result<()> {
         .connect(&db_url)
         .await
         .unwrap();
+    run_migrations(&pool)
+        .await
+        .expect("Failed to apply database migrations.");
 
     let account = Arc::new(LocalAccount::from_private_key(&admin_priv_key, 0).unwrap());
     let rest_client = Arc::new(Client::new(TESTNET_NODE.parse().unwrap()));
     
-    let res = rest_client.get_account(account.address()).await.unwrap();
-    account.set_sequence_number(res.inner().sequence_number);
+    // a replay runs without the network, and never sends transactions for the requests it replays
+    let replaying = replay_file.is_some();
+    if replaying {
+        println!("Replay mode: no transaction is sent to the chain");
+    } else {
+        let res = rest_client.get_account(account.address()).await.unwrap();
+        account.set_sequence_number(res.inner().sequence_number);
+    }
 
+    // bounded so that a burst of requests slows the listener down instead of piling up events
     let (sender_events, mut receiver_events) =
-        tokio::sync::mpsc::unbounded_channel::<ContractEvent>();
+        tokio::sync::mpsc::channel::<EventEnvelope>(EVENT_QUEUE_SIZE);
 
+    // events that could not be decoded are logged and skipped
+    let (sender_decode_errors, mut receiver_decode_errors) =
+        tokio::sync::mpsc::unbounded_channel::<DecodeError>();
     tokio::spawn(async move {
-        run_listener(
-            &auth_token,
-            INDEXER_URL,
-            CONTRACT_MODULE.to_owned(),
-            sender_events,
-        )
-        .await
-        .unwrap();
+        while let Some(e) = receiver_decode_errors.recv().await {
+            println!("{}, raw event: {}", e, e.event.data);
+        }
     });
 
-    let temp_account = account.clone();
-    let temp_rest_client = rest_client.clone();
+    // resume after the last event handled, so that the requests created while the orchestrator
+    // was down are not missed. A replay does not move the cursor of the chain
+    let mut cursor = match replay_file {
+        Some(_) => None,
+        None => load_cursor(&pool)
+            .await
+            .expect("Failed to read the listener cursor."),
+    };
+    let start_version = cursor.map(|cursor| cursor.version);
+    match start_version {
+        Some(version) => println!("Resuming events from version {}", version),
+        None => println!("No listener cursor found: starting from the chain tip"),
+    }
+
+    // a recorded event stream can be replayed instead of listening to the chain
+    let mut source: Box<dyn EventSource> = match (replay_file.to_owned(), auth_token) {
+        (Some(replay_file), _) => {
+            println!("Replaying events from {}", replay_file);
+            Box::new(ReplayEventSource::open(replay_file, replay_speed).await.unwrap())
+        }
+        (None, Some(auth_token)) => {
+            let source = GrpcEventSource::new(&auth_token, INDEXER_URL)
+                .with_config(listener_config)
+                .with_chain_tip_from(TESTNET_NODE);
+            Box::new(match start_version {
+                Some(version) => source.starting_from(version),
+                None => source,
+            })
+        }
+        (None, None) => {
+            println!("INDEXER_AUTH_KEY not set: polling the fullnode for events");
+            let source = RestEventSource::new(TESTNET_NODE);
+            Box::new(match start_version {
+                Some(version) => source.starting_from(version),
+                None => source,
+            })
+        }
+    };
+    if let Some(record_file) = record_file {
+        println!("Recording events to {}", record_file);
+        source = Box::new(
+            RecordingEventSource::new(source, CONTRACT_MODULE.to_owned(), record_file)
+                .await
+                .unwrap(),
+        );
+    }
+
+    let listener_stats = run_envelope_listener_with_source(
+        source,
+        CONTRACT_MODULE.to_owned(),
+        sender_events,
+        sender_decode_errors,
+    )
+    .await
+    .unwrap();
+
+    let report_stats = listener_stats.clone();
     tokio::spawn(async move {
+        let listener_stats = report_stats;
+        loop {
+            sleep(LISTENER_REPORT_INTERVAL).await;
+            println!(
+                "Chain listener at version {}, lag: {:?}, queued events: {:?}",
+                listener_stats.latest_version(),
+                listener_stats.version_lag(),
+                listener_stats.queue_depth()
+            );
+        }
+    });
+
+    let tracker = RequestTracker::new(pool.clone());
+    let scheduler = FinalizationScheduler::new(
+        pool.clone(),
+        account.clone(),
+        rest_client.clone(),
+        tracker.clone(),
+        std::env::var("AUCTION_CLOSED_ABORT_CODE")
+            .ok()
+            .map(|code| code.parse().expect("AUCTION_CLOSED_ABORT_CODE must be a number.")),
+    );
+    if !replaying {
+        scheduler
+            .resume()
+            .await
+            .expect("Failed to resume the pending finalizations.");
+    }
+
+    let deadlines = DeliveryDeadlines::new(
+        pool.clone(),
+        account.clone(),
+        rest_client.clone(),
+        tracker.clone(),
+        DeadlineConfig::from_env(),
+    );
+    if !replaying {
+        deadlines
+            .resume()
+            .await
+            .expect("Failed to resume the pending delivery deadlines.");
+    }
+
+    let temp_tracker = tracker.clone();
+    let temp_deadlines = deadlines.clone();
+    let cursor_pool = pool.clone();
+    tokio::spawn(async move {
+        // events of the transaction the previous run stopped at that were already handled
+        let resume = cursor;
+        let mut skipped = 0;
         while let Some(e) = receiver_events.recv().await {
-            if let ContractEvent::OnNewWorkRequest(new_work_request) = e {
-                println!(
-                    "Request {}: Scheduling auction finalization",
-                    new_work_request.request_id
-                );
-                let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
-                let now_as_instant = Instant::now();
-                let target_time = now_as_instant
-                    + Duration::from_micros(new_work_request.time_limit + DELTA_TIME)
-                    - now;
-
-                let task_account = temp_account.clone();
-                let task_client = temp_rest_client.clone();
-                tokio::spawn(async move {
-                    // Calculate the target time as an Instant
-                    sleep_until(target_time).await;
-
-                    println!(
-                        "Request {}: Sending finalization",
-                        new_work_request.request_id
-                    );
+            if let Some(resume) = resume {
+                if e.version == resume.version && skipped < resume.events {
+                    skipped += 1;
+                    continue;
+                }
+            }
 
-                    let mut finalization_successful = false;
-                    let max_try = 5;
-                    let mut curr_try = 0;
-                    while !finalization_successful && curr_try < max_try {
-                        let finalization_res = match finalize_auction(
-                            new_work_request.request_id,
-                            &task_account,
-                            &task_client,
-                        )
-                        .await
-                        {
-                            Ok(tx) => tx,
-                            Err(_e) => {
-                                // tx failed, possibly due to invalid sequence number
-                                let res = task_client.get_account(task_account.address()).await.unwrap();
-                                task_account.set_sequence_number(res.inner().sequence_number);
-                                curr_try += 1;
-                                continue;
-                            }
-                        };
-
-                        // wait for tx to be processed
-                        let temp = finalization_res; //.unwrap();
-                        let inner = temp.inner();
-                        match task_client
-                            .wait_for_transaction_by_hash(
-                                inner.hash.into(),
-                                inner.request.expiration_timestamp_secs.into(),
-                                None,
-                                None,
-                            )
-                            .await
-                        {
-                            Ok(v) => {
-                                let v = v.inner();
-                                match v {
-                                    Transaction::UserTransaction(user_tx) => {
-                                        if user_tx.info.success {
-                                            finalization_successful = true;
-                                        } else {
-                                            curr_try += 1;
-                                        }
-                                    }
-                                    _ => {
-                                        curr_try += 1;
-                                    }
-                                }
-                            }
-                            Err(_) => {
-                                curr_try += 1;
-                            }
-                        }
-                    }
+            temp_tracker.handle_event(&e).await;
 
-                    if finalization_successful {
-                        println!("Request {}: Auction finalized", new_work_request.request_id);
-                    } else {
-                        println!("Request {}: Auction failed to finalize", new_work_request.request_id);
+            // a replay only rebuilds the lifecycle of the requests, nothing is sent to the chain
+            if replaying {
+                continue;
+            }
+
+            match e.event {
+                ContractEvent::OnNewWorkRequest(new_work_request) => {
+                    let due_at = new_work_request.time_limit + DELTA_TIME;
+                    if let Err(e) = scheduler.schedule(new_work_request.request_id, due_at).await {
+                        println!(
+                            "Request {}: failed to schedule auction finalization: {}",
+                            new_work_request.request_id, e
+                        );
+                    }
+                }
+                ContractEvent::OnBidWon(bid_won) => {
+                    if let Err(err) = temp_deadlines
+                        .start(bid_won.request_id, &bid_won.winner, e.timestamp_us)
+                        .await
+                    {
+                        println!(
+                            "Request {}: failed to start delivery deadline: {}",
+                            bid_won.request_id, err
+                        );
                     }
-                });
+                }
+                _ => {
+                    // ignore event
+                }
             }
-            else {
-                // ignore event 
+
+            let next = cursor.get_or_insert_with(Cursor::default);
+            next.advance(e.version);
+            if let Err(err) = save_cursor(&cursor_pool, next).await {
+                println!("Failed to save the listener cursor at version {}: {}", e.version, err);
             }
         }
     });
@@ -494,6 +841,15 @@ async fn main() -> std::io::Result<()> {
         wallet: account.clone(),
         rest_client: rest_client.clone(),
         db_pool: pool,
+        listener_stats,
+        tracker,
+        challenges: ChallengeStore::default(),
+        auth_keys: AuthKeyCache::default(),
+        store,
+        media_limits: MediaLimits::from_env(),
+        deadlines,
+        render_locks: RenderLocks::default(),
+        replaying,
     });
 
     HttpServer::new(move || {
@@ -507,11 +863,24 @@ async fn main() -> std::io::Result<()> {
             )
             .app_data(app_state.clone())
             .service(request_details)
+            .service(request_payload_challenge)
             .service(request_payload)
+            .service(sealed::register_encryption_key)
+            .service(sealed::winner_encryption_key)
+            .service(sealed::upload_sealed_payload)
             .service(submit_text)
             .service(submit_image)
             .service(submit_voice)
             .service(get_output)
+            .service(metrics)
+            .service(status::request_status)
+            .service(status::request_events)
+            .service(receipts::request_receipt)
+            .service(market::request_bids)
+            .service(market::price_stats)
+            .service(market::bid_count_stats)
+            .service(market::fill_time_stats)
+            .service(market::worker_stats)
     })
     .bind(("127.0.0.1", orchestrator_port.parse().unwrap()))?
     .run()
@@ -0,0 +1,195 @@
+use actix_web::http::StatusCode;
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::{Deserialize, Serialize};
+
+use crate::AppState;
+
+// most active workers listed by `/market/stats/workers`
+const MAX_WORKERS: i64 = 100;
+
+#[derive(sqlx::FromRow, Serialize)]
+struct BidDb {
+    pub bidder: String,
+    pub price: i64,
+    /// Unix time of the transaction that placed the bid, in milliseconds
+    pub at_ms: i64,
+}
+
+#[derive(Debug, Deserialize)]
+struct PriceQuery {
+    pub task_type: Option<String>,
+    pub model: Option<String>,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+struct PriceStatsDb {
+    pub task_type: String,
+    pub model: String,
+    pub bids: i64,
+    pub min: i64,
+    pub p10: f64,
+    pub p25: f64,
+    pub median: f64,
+    pub p75: f64,
+    pub p90: f64,
+    pub max: i64,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+struct BidCountStatsDb {
+    pub task_type: String,
+    pub requests: i64,
+    pub without_bids: i64,
+    pub mean: f64,
+    pub median: f64,
+    pub max: i64,
+}
+
+#[derive(sqlx::FromRow, Serialize)]
+struct FillTimeStatsDb {
+    pub task_type: String,
+    pub requests: i64,
+    pub median_ms: f64,
+    pub p90_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(sqlx::FromRow)]
+struct WorkerStatsDb {
+    pub bidder: String,
+    pub bids: i64,
+    pub requests: i64,
+    pub wins: i64,
+    pub median_price: f64,
+}
+
+#[derive(Serialize)]
+struct WorkerStats {
+    pub bidder: String,
+    pub bids: i64,
+    pub requests: i64,
+    pub wins: i64,
+    /// Share of the auctions the worker bid on that it won
+    pub win_rate: f64,
+    pub median_price: f64,
+}
+
+fn json_response<T: Serialize>(stats: Result<T, sqlx::Error>) -> HttpResponse {
+    match stats {
+        Ok(stats) => HttpResponse::Ok().json(stats),
+        Err(e) => {
+            println!("Failed to read the bid book: {}", e);
+            HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// Bids placed on the request, in the order they were placed on chain.
+#[get("/requests/{id}/bids")]
+async fn request_bids(id: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+    let bids = sqlx::query_as::<_, BidDb>(
+        "SELECT bidder, price, (extract(epoch from created_at) * 1000)::BIGINT as at_ms \
+         from bids where request_id=$1 order by created_at, id;",
+    )
+    .bind(*id as i64)
+    .fetch_all(&app_state.db_pool)
+    .await;
+
+    json_response(bids)
+}
+
+/// Percentiles of the bid prices per task type and model, optionally filtered on them.
+#[get("/market/stats/prices")]
+async fn price_stats(query: web::Query<PriceQuery>, app_state: web::Data<AppState>) -> impl Responder {
+    let stats = sqlx::query_as::<_, PriceStatsDb>(
+        "SELECT p.task_type, p.model, count(*) as bids, min(b.price) as min, \
+         percentile_cont(0.1) WITHIN GROUP (ORDER BY b.price) as p10, \
+         percentile_cont(0.25) WITHIN GROUP (ORDER BY b.price) as p25, \
+         percentile_cont(0.5) WITHIN GROUP (ORDER BY b.price) as median, \
+         percentile_cont(0.75) WITHIN GROUP (ORDER BY b.price) as p75, \
+         percentile_cont(0.9) WITHIN GROUP (ORDER BY b.price) as p90, \
+         max(b.price) as max \
+         from bids b join payloads p on p.request_id = b.request_id \
+         where ($1::TEXT IS NULL OR p.task_type = $1) and ($2::TEXT IS NULL OR p.model = $2) \
+         group by p.task_type, p.model order by p.task_type, p.model;",
+    )
+    .bind(&query.task_type)
+    .bind(&query.model)
+    .fetch_all(&app_state.db_pool)
+    .await;
+
+    json_response(stats)
+}
+
+/// Number of bids per request, per task type, counting the requests that received none.
+#[get("/market/stats/bids")]
+async fn bid_count_stats(app_state: web::Data<AppState>) -> impl Responder {
+    let stats = sqlx::query_as::<_, BidCountStatsDb>(
+        "WITH counts AS ( \
+             SELECT s.request_id, count(b.id) as bids from request_status s \
+             left join bids b on b.request_id = s.request_id group by s.request_id \
+         ) \
+         SELECT coalesce(p.task_type, 'Unknown') as task_type, count(*) as requests, \
+         count(*) FILTER (WHERE c.bids = 0) as without_bids, avg(c.bids)::FLOAT8 as mean, \
+         percentile_cont(0.5) WITHIN GROUP (ORDER BY c.bids) as median, max(c.bids) as max \
+         from counts c left join payloads p on p.request_id = c.request_id \
+         group by 1 order by 1;",
+    )
+    .fetch_all(&app_state.db_pool)
+    .await;
+
+    json_response(stats)
+}
+
+/// Time from the creation of a request to the end of its auction with a winner, per task type.
+#[get("/market/stats/fill-times")]
+async fn fill_time_stats(app_state: web::Data<AppState>) -> impl Responder {
+    let stats = sqlx::query_as::<_, FillTimeStatsDb>(
+        "WITH fills AS ( \
+             SELECT created.request_id, \
+             (extract(epoch from (assigned.created_at - created.created_at)) * 1000)::FLOAT8 as ms \
+             from request_transitions created join request_transitions assigned \
+             on assigned.request_id = created.request_id and assigned.to_status = 'Assigned' \
+             where created.to_status = 'Created' \
+         ) \
+         SELECT coalesce(p.task_type, 'Unknown') as task_type, count(*) as requests, \
+         percentile_cont(0.5) WITHIN GROUP (ORDER BY f.ms) as median_ms, \
+         percentile_cont(0.9) WITHIN GROUP (ORDER BY f.ms) as p90_ms, max(f.ms) as max_ms \
+         from fills f left join payloads p on p.request_id = f.request_id \
+         group by 1 order by 1;",
+    )
+    .fetch_all(&app_state.db_pool)
+    .await;
+
+    json_response(stats)
+}
+
+/// Bids, wins and win rate of the most active workers.
+#[get("/market/stats/workers")]
+async fn worker_stats(app_state: web::Data<AppState>) -> impl Responder {
+    let stats = sqlx::query_as::<_, WorkerStatsDb>(
+        "SELECT b.bidder, count(*) as bids, count(DISTINCT b.request_id) as requests, \
+         count(DISTINCT s.request_id) as wins, \
+         percentile_cont(0.5) WITHIN GROUP (ORDER BY b.price) as median_price \
+         from bids b left join request_status s on s.request_id = b.request_id and s.winner = b.bidder \
+         group by b.bidder order by requests desc limit $1;",
+    )
+    .bind(MAX_WORKERS)
+    .fetch_all(&app_state.db_pool)
+    .await
+    .map(|stats| {
+        stats
+            .into_iter()
+            .map(|worker| WorkerStats {
+                win_rate: worker.wins as f64 / worker.requests as f64,
+                bidder: worker.bidder,
+                bids: worker.bids,
+                requests: worker.requests,
+                wins: worker.wins,
+                median_price: worker.median_price,
+            })
+            .collect::<Vec<_>>()
+    });
+
+    json_response(stats)
+}
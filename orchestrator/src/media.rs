@@ -0,0 +1,165 @@
+use std::fmt;
+use std::io::Cursor;
+
+use actix_web::http::StatusCode;
+use actix_web::ResponseError;
+use image::ImageFormat;
+use symphonia::core::codecs::CODEC_TYPE_NULL;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+const DEFAULT_MAX_IMAGE_SIZE: usize = 20 * 1024 * 1024;
+const DEFAULT_MAX_AUDIO_SIZE: usize = 50 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MediaKind {
+    Image,
+    Audio,
+}
+
+impl fmt::Display for MediaKind {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaKind::Image => write!(f, "image"),
+            MediaKind::Audio => write!(f, "audio"),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub enum MediaError {
+    Empty,
+    TooLarge { max_size: usize },
+    /// The content is not in any of the supported formats
+    UnknownType,
+    /// An image submitted as a voice, or the other way around
+    Unexpected { mime: &'static str, expected: MediaKind },
+    /// The header or the content does not decode
+    Corrupted(String),
+}
+
+impl fmt::Display for MediaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MediaError::Empty => write!(f, "Empty submission"),
+            MediaError::TooLarge { max_size } => {
+                write!(f, "Submission larger than {} bytes", max_size)
+            }
+            MediaError::UnknownType => write!(f, "Unsupported media type"),
+            MediaError::Unexpected { mime, expected } => {
+                write!(f, "Expected {}, got {}", expected, mime)
+            }
+            MediaError::Corrupted(e) => write!(f, "Invalid media: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MediaError {}
+
+impl ResponseError for MediaError {
+    fn status_code(&self) -> StatusCode {
+        match self {
+            MediaError::Empty | MediaError::Corrupted(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            MediaError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+            MediaError::UnknownType | MediaError::Unexpected { .. } => {
+                StatusCode::UNSUPPORTED_MEDIA_TYPE
+            }
+        }
+    }
+}
+
+/// Maximum size of the submitted files, from `MAX_IMAGE_SIZE` and `MAX_AUDIO_SIZE` (bytes)
+#[derive(Debug, Clone, Copy)]
+pub struct MediaLimits {
+    pub max_image_size: usize,
+    pub max_audio_size: usize,
+}
+
+impl MediaLimits {
+    pub fn from_env() -> Self {
+        let size = |name: &str, default: usize| {
+            std::env::var(name)
+                .map(|size| {
+                    size.parse()
+                        .unwrap_or_else(|_| panic!("{} must be a number of bytes.", name))
+                })
+                .unwrap_or(default)
+        };
+
+        MediaLimits {
+            max_image_size: size("MAX_IMAGE_SIZE", DEFAULT_MAX_IMAGE_SIZE),
+            max_audio_size: size("MAX_AUDIO_SIZE", DEFAULT_MAX_AUDIO_SIZE),
+        }
+    }
+
+    pub fn max_size(&self, kind: MediaKind) -> usize {
+        match kind {
+            MediaKind::Image => self.max_image_size,
+            MediaKind::Audio => self.max_audio_size,
+        }
+    }
+}
+
+/// MIME type of `content` from its magic bytes, for the supported formats.
+pub fn sniff(content: &[u8]) -> Option<&'static str> {
+    match content {
+        [0xFF, 0xD8, 0xFF, ..] => Some("image/jpeg"),
+        [0x89, b'P', b'N', b'G', 0x0D, 0x0A, 0x1A, 0x0A, ..] => Some("image/png"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'E', b'B', b'P', ..] => Some("image/webp"),
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Some("audio/wav"),
+        [b'f', b'L', b'a', b'C', ..] => Some("audio/flac"),
+        [b'O', b'g', b'g', b'S', ..] => Some("audio/ogg"),
+        [b'I', b'D', b'3', ..] => Some("audio/mpeg"),
+        // frame sync of an MPEG audio stream without ID3 tag
+        [0xFF, second, ..] if second & 0xE0 == 0xE0 => Some("audio/mpeg"),
+        _ => None,
+    }
+}
+
+fn check_image(content: &[u8], mime: &str) -> Result<(), MediaError> {
+    let format = ImageFormat::from_mime_type(mime).ok_or(MediaError::UnknownType)?;
+    image::load_from_memory_with_format(content, format)
+        .map_err(|e| MediaError::Corrupted(e.to_string()))?;
+    Ok(())
+}
+
+fn check_audio(content: &[u8], mime: &str) -> Result<(), MediaError> {
+    let mut hint = Hint::new();
+    hint.mime_type(mime);
+    let source = MediaSourceStream::new(Box::new(Cursor::new(content.to_vec())), Default::default());
+
+    let probed = symphonia::default::get_probe()
+        .format(&hint, source, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| MediaError::Corrupted(e.to_string()))?;
+    let track = probed
+        .format
+        .default_track()
+        .ok_or(MediaError::Corrupted("no audio track".to_owned()))?;
+    if track.codec_params.codec == CODEC_TYPE_NULL || track.codec_params.sample_rate.is_none() {
+        return Err(MediaError::Corrupted("unknown codec".to_owned()));
+    }
+    Ok(())
+}
+
+/// Checks that `content` is a well formed file of `kind` within the size limit,
+/// returns its MIME type. Decoding is CPU bound, call it from a blocking task.
+pub fn validate(content: &[u8], kind: MediaKind, limits: &MediaLimits) -> Result<&'static str, MediaError> {
+    if content.is_empty() {
+        return Err(MediaError::Empty);
+    }
+    let max_size = limits.max_size(kind);
+    if content.len() > max_size {
+        return Err(MediaError::TooLarge { max_size });
+    }
+
+    let mime = sniff(content).ok_or(MediaError::UnknownType)?;
+    match kind {
+        MediaKind::Image if mime.starts_with("image/") => check_image(content, mime)?,
+        MediaKind::Audio if mime.starts_with("audio/") => check_audio(content, mime)?,
+        expected => return Err(MediaError::Unexpected { mime, expected }),
+    }
+
+    Ok(mime)
+}
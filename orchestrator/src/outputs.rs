@@ -0,0 +1,212 @@
+use std::time::Duration;
+
+use actix_files::HttpRange;
+use actix_web::http::header::{
+    EntityTag, ETag, Header, IfNoneMatch, ACCEPT_RANGES, CONTENT_RANGE, IF_RANGE, LOCATION, RANGE,
+};
+use actix_web::http::StatusCode;
+use actix_web::{error, HttpRequest, HttpResponse};
+use proxirun_sdk::submission::content_hash;
+use sqlx::{Pool, Postgres};
+
+use crate::storage::blob_key;
+use crate::variants::{ensure_variant, Variant, VariantQuery};
+use crate::AppState;
+
+const PRESIGNED_URL_EXPIRY: Duration = Duration::from_secs(300);
+
+/// A file in the result store: an output or one of its variants
+#[derive(sqlx::FromRow)]
+pub struct OutputDb {
+    pub content_type: String,
+    pub content_hash: String,
+    pub location: String,
+}
+
+async fn fetch_output(pool: &Pool<Postgres>, request_id: u64) -> Result<Option<OutputDb>, sqlx::Error> {
+    sqlx::query_as::<_, OutputDb>(
+        "SELECT outputs.content_type, outputs.content_hash, blobs.location \
+         from outputs join blobs on blobs.content_hash = outputs.content_hash where request_id=$1;",
+    )
+    .bind(request_id as i64)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Stores the file of an image or voice submission under its SHA-256, unless an identical
+/// file is already stored, and maps the request to it. Returns the hex encoded hash,
+/// or 409 if the request already has an output.
+pub async fn store_output(
+    app_state: &AppState,
+    id: u64,
+    content_type: &str,
+    content: &[u8],
+) -> Result<String, actix_web::Error> {
+    let hash = hex::encode(content_hash(content));
+
+    let stored: Option<(String,)> = sqlx::query_as("SELECT location from blobs where content_hash=$1;")
+        .bind(&hash)
+        .fetch_optional(&app_state.db_pool)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    if stored.is_none() {
+        let key = blob_key(&hash);
+        app_state
+            .store
+            .put(&key, content, content_type)
+            .await
+            .map_err(error::ErrorInternalServerError)?;
+
+        // a concurrent upload of the same file wrote the same bytes under the same key
+        sqlx::query(
+            "INSERT into blobs (content_hash, location, size) values ($1, $2, $3) ON CONFLICT (content_hash) DO NOTHING;",
+        )
+        .bind(&hash)
+        .bind(&key)
+        .bind(content.len() as i64)
+        .execute(&app_state.db_pool)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    } else {
+        println!("Request {}: output already stored as {}", id, hash);
+    }
+
+    // outputs are write-once, the receipt signed on commit binds the request to this hash
+    let res = sqlx::query(
+        "INSERT into outputs (request_id, content_type, content_hash) values ($1, $2, $3) \
+         ON CONFLICT (request_id) DO NOTHING;",
+    )
+    .bind(id as i64)
+    .bind(content_type)
+    .bind(&hash)
+    .execute(&app_state.db_pool)
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    if res.rows_affected() == 0 {
+        println!("Request {}: rejected a second output", id);
+        return Err(error::ErrorConflict("An output was already submitted for this request"));
+    }
+
+    Ok(hash)
+}
+
+/// 304 response if the client already holds the representation tagged `content_hash`.
+pub fn not_modified(req: &HttpRequest, content_hash: &str) -> Option<HttpResponse> {
+    let etag = EntityTag::new_strong(content_hash.to_owned());
+    let matches = match IfNoneMatch::parse(req) {
+        Ok(IfNoneMatch::Any) => true,
+        Ok(IfNoneMatch::Items(tags)) => tags.iter().any(|tag| tag.weak_eq(&etag)),
+        Err(_) => false,
+    };
+
+    matches.then(|| HttpResponse::NotModified().insert_header(ETag(etag)).finish())
+}
+
+pub fn etag(content_hash: &str) -> ETag {
+    ETag(EntityTag::new_strong(content_hash.to_owned()))
+}
+
+/// Byte range asked by the `Range` header, `None` for the whole content. The range is
+/// ignored when `If-Range` names another version of the file. `Err` if it cannot be satisfied.
+fn requested_range(req: &HttpRequest, size: u64, content_hash: &str) -> Result<Option<HttpRange>, ()> {
+    let range = match req.headers().get(RANGE).and_then(|range| range.to_str().ok()) {
+        Some(range) => range,
+        None => return Ok(None),
+    };
+    if let Some(if_range) = req.headers().get(IF_RANGE) {
+        if if_range.to_str().ok() != Some(etag(content_hash).0.to_string().as_str()) {
+            return Ok(None);
+        }
+    }
+
+    // only the first range is served, as NamedFile does
+    match HttpRange::parse(range, size) {
+        Ok(ranges) => Ok(ranges.into_iter().next()),
+        Err(_) => Err(()),
+    }
+}
+
+/// Serves a stored image or voice result, or the variant of it asked by `query`.
+pub async fn serve_output(
+    app_state: &AppState,
+    id: u64,
+    query: &VariantQuery,
+    req: &HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    let output = match fetch_output(&app_state.db_pool, id).await {
+        Ok(Some(output)) => output,
+        Ok(None) => return Ok(HttpResponse::new(StatusCode::NOT_FOUND)),
+        Err(e) => return Err(error::ErrorInternalServerError(e)),
+    };
+    let output = match Variant::from_query(query, &output.content_type)? {
+        Some(variant) => ensure_variant(app_state, &output, variant).await?,
+        None => output,
+    };
+
+    serve_stored(app_state, id, output, req).await
+}
+
+/// Sends a stored file tagged with its SHA-256, or the byte range asked by the client.
+/// Redirects to the store when it hands out presigned URLs, otherwise checks the file
+/// against its hash before sending it.
+async fn serve_stored(
+    app_state: &AppState,
+    id: u64,
+    output: OutputDb,
+    req: &HttpRequest,
+) -> Result<HttpResponse, actix_web::Error> {
+    if let Some(response) = not_modified(req, &output.content_hash) {
+        return Ok(response);
+    }
+
+    let url = app_state
+        .store
+        .presigned_url(&output.location, PRESIGNED_URL_EXPIRY)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    if let Some(url) = url {
+        return Ok(HttpResponse::TemporaryRedirect()
+            .insert_header((LOCATION, url))
+            .finish());
+    }
+
+    let content = match app_state.store.get(&output.location).await {
+        Ok(Some(content)) => content,
+        Ok(None) => return Ok(HttpResponse::new(StatusCode::NOT_FOUND)),
+        Err(e) => return Err(error::ErrorInternalServerError(e)),
+    };
+    if hex::encode(content_hash(&content)) != output.content_hash {
+        println!(
+            "Request {}: stored output {} does not match its hash",
+            id, output.location
+        );
+        return Err(error::ErrorInternalServerError("Corrupted output"));
+    }
+
+    let size = content.len() as u64;
+    match requested_range(req, size, &output.content_hash) {
+        Ok(Some(range)) => {
+            let start = range.start as usize;
+            let end = start + range.length as usize;
+            Ok(HttpResponse::PartialContent()
+                .content_type(output.content_type)
+                .insert_header(etag(&output.content_hash))
+                .insert_header((ACCEPT_RANGES, "bytes"))
+                .insert_header((
+                    CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, end - 1, size),
+                ))
+                .body(content[start..end].to_vec()))
+        }
+        Ok(None) => Ok(HttpResponse::Ok()
+            .content_type(output.content_type)
+            .insert_header(etag(&output.content_hash))
+            .insert_header((ACCEPT_RANGES, "bytes"))
+            .body(content)),
+        Err(()) => Ok(HttpResponse::RangeNotSatisfiable()
+            .insert_header((CONTENT_RANGE, format!("bytes */{}", size)))
+            .finish()),
+    }
+}
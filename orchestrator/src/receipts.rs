@@ -0,0 +1,70 @@
+use actix_web::http::StatusCode;
+use actix_web::{get, web, HttpResponse, Responder};
+use proxirun_sdk::receipt::DeliveryReceipt;
+use sqlx::{Pool, Postgres};
+
+use crate::AppState;
+
+#[derive(sqlx::FromRow)]
+struct ReceiptDb {
+    pub request_id: i64,
+    pub content_hash: String,
+    pub commit_transaction: String,
+    pub orchestrator: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// Keeps the receipt of the last committed submission of the request.
+pub async fn store_receipt(pool: &Pool<Postgres>, receipt: &DeliveryReceipt) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT into receipts (request_id, content_hash, commit_transaction, orchestrator, public_key, signature) \
+         values ($1, $2, $3, $4, $5, $6) \
+         ON CONFLICT (request_id) DO UPDATE SET content_hash=$2, commit_transaction=$3, orchestrator=$4, \
+         public_key=$5, signature=$6, created_at=now();",
+    )
+    .bind(receipt.request_id as i64)
+    .bind(&receipt.content_hash)
+    .bind(&receipt.commit_transaction)
+    .bind(&receipt.orchestrator)
+    .bind(&receipt.public_key)
+    .bind(&receipt.signature)
+    .execute(pool)
+    .await?;
+
+    Ok(())
+}
+
+pub async fn fetch_receipt(
+    pool: &Pool<Postgres>,
+    request_id: u64,
+) -> Result<Option<DeliveryReceipt>, sqlx::Error> {
+    let receipt = sqlx::query_as::<_, ReceiptDb>(
+        "SELECT request_id, content_hash, commit_transaction, orchestrator, public_key, signature \
+         from receipts where request_id=$1;",
+    )
+    .bind(request_id as i64)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(receipt.map(|r| DeliveryReceipt {
+        request_id: r.request_id as u64,
+        content_hash: r.content_hash,
+        commit_transaction: r.commit_transaction,
+        orchestrator: r.orchestrator,
+        public_key: r.public_key,
+        signature: r.signature,
+    }))
+}
+
+#[get("/requests/{id}/receipt")]
+async fn request_receipt(id: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+    match fetch_receipt(&app_state.db_pool, *id).await {
+        Ok(Some(receipt)) => HttpResponse::Ok().json(receipt),
+        Ok(None) => HttpResponse::new(StatusCode::NOT_FOUND),
+        Err(e) => {
+            println!("Request {}: failed to read receipt: {}", *id, e);
+            HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
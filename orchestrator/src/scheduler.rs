@@ -0,0 +1,219 @@
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use aptos_sdk::rest_client::{Client, Transaction};
+use aptos_sdk::types::LocalAccount;
+use proxirun_sdk::contract_interact::{
+    contract_abort_code, finalize_auction, simulate_finalize_auction,
+};
+use sqlx::{Pool, Postgres};
+use tokio::time::sleep;
+
+use crate::lifecycle::{RequestStatus, RequestTracker};
+
+const MAX_FINALIZATION_TRIES: usize = 5;
+
+/// Auction finalizations, stored before they are armed so that a restart does not forget them.
+#[derive(Clone)]
+pub struct FinalizationScheduler {
+    pool: Pool<Postgres>,
+    account: Arc<LocalAccount>,
+    rest_client: Arc<Client>,
+    tracker: RequestTracker,
+    // abort code of `finalize_auction` when the auction is not open anymore
+    closed_abort_code: Option<u64>,
+}
+
+fn now_micros() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_micros() as u64
+}
+
+impl FinalizationScheduler {
+    pub fn new(
+        pool: Pool<Postgres>,
+        account: Arc<LocalAccount>,
+        rest_client: Arc<Client>,
+        tracker: RequestTracker,
+        closed_abort_code: Option<u64>,
+    ) -> Self {
+        FinalizationScheduler {
+            pool,
+            account,
+            rest_client,
+            tracker,
+            closed_abort_code,
+        }
+    }
+
+    /// Stores the finalization of `request_id` due at `due_at_micros` (Unix time) and arms it.
+    /// A finalization already stored, e.g. when the event is received again after a restart, is left as is.
+    pub async fn schedule(&self, request_id: u64, due_at_micros: u64) -> Result<(), sqlx::Error> {
+        let res = sqlx::query(
+            "INSERT into finalizations (request_id, due_at) values ($1, to_timestamp($2 / 1000000.0)) \
+             ON CONFLICT (request_id) DO NOTHING;",
+        )
+        .bind(request_id as i64)
+        .bind(due_at_micros as i64)
+        .execute(&self.pool)
+        .await?;
+
+        if res.rows_affected() > 0 {
+            println!("Request {}: Scheduling auction finalization", request_id);
+            self.arm(request_id, due_at_micros);
+        }
+        Ok(())
+    }
+
+    /// Re-arms the finalizations left pending by the previous run. The overdue ones are first
+    /// reconciled with the chain, the auction may have been finalized before the restart.
+    pub async fn resume(&self) -> Result<(), sqlx::Error> {
+        let pending: Vec<(i64, i64)> = sqlx::query_as(
+            "SELECT request_id, (extract(epoch from due_at) * 1000000)::BIGINT from finalizations \
+             where state='pending' order by due_at;",
+        )
+        .fetch_all(&self.pool)
+        .await?;
+
+        println!("Resuming {} pending auction finalizations", pending.len());
+
+        for (request_id, due_at_micros) in pending {
+            let (request_id, due_at_micros) = (request_id as u64, due_at_micros as u64);
+            if due_at_micros > now_micros() {
+                self.arm(request_id, due_at_micros);
+                continue;
+            }
+
+            match self.is_finalized(request_id).await {
+                Some(detail) => {
+                    println!("Request {}: Auction already finalized ({})", request_id, detail);
+                    self.record(request_id, "done", 0, Some(&detail)).await;
+                }
+                None => self.arm(request_id, due_at_micros),
+            }
+        }
+        Ok(())
+    }
+
+    /// Why the auction of `request_id` needs no finalization anymore, `None` if it still does.
+    async fn is_finalized(&self, request_id: u64) -> Option<String> {
+        // the outcome of the auction may have been received before the restart
+        match self.tracker.status(request_id).await {
+            Ok(Some(status)) if status.is_final() || status.winner().is_some() => {
+                return Some(format!("request is {}", status.name()));
+            }
+            Ok(_) => (),
+            Err(e) => println!("Request {}: failed to read status: {}", request_id, e),
+        }
+
+        // the contract has no view on the auctions, simulating the finalization tells whether it is still open.
+        // only its own "closed" abort counts, a stale sequence number or a lack of gas says nothing about the auction
+        let closed_abort_code = self.closed_abort_code?;
+        match simulate_finalize_auction(request_id, &self.account, &self.rest_client).await {
+            Ok(simulated) => match simulated.inner().first() {
+                Some(txn) if !txn.info.success => {
+                    if contract_abort_code(&txn.info.vm_status) == Some(closed_abort_code) {
+                        return Some(format!("finalization aborts: {}", txn.info.vm_status));
+                    }
+                    println!(
+                        "Request {}: finalization simulation failed: {}",
+                        request_id, txn.info.vm_status
+                    );
+                    None
+                }
+                _ => None,
+            },
+            Err(e) => {
+                // finalizing anyway is harmless, the transaction aborts if it is not needed
+                println!("Request {}: failed to simulate finalization: {}", request_id, e);
+                None
+            }
+        }
+    }
+
+    fn arm(&self, request_id: u64, due_at_micros: u64) {
+        let scheduler = self.clone();
+        tokio::spawn(async move {
+            sleep(Duration::from_micros(due_at_micros.saturating_sub(now_micros()))).await;
+            scheduler.finalize(request_id).await;
+        });
+    }
+
+    async fn record(&self, request_id: u64, state: &str, attempts: usize, detail: Option<&str>) {
+        let res = sqlx::query(
+            "UPDATE finalizations SET state=$2, attempts=attempts + $3, detail=$4, updated_at=now() where request_id=$1;",
+        )
+        .bind(request_id as i64)
+        .bind(state)
+        .bind(attempts as i32)
+        .bind(detail)
+        .execute(&self.pool)
+        .await;
+
+        if let Err(e) = res {
+            println!("Request {}: failed to record finalization: {}", request_id, e);
+        }
+    }
+
+    /// Sends the finalization, retrying with a fresh sequence number when it fails.
+    async fn finalize(&self, request_id: u64) {
+        self.tracker
+            .track(request_id, RequestStatus::Finalizing, None)
+            .await;
+
+        println!("Request {}: Sending finalization", request_id);
+
+        let mut finalization_successful = false;
+        let mut curr_try = 0;
+        while !finalization_successful && curr_try < MAX_FINALIZATION_TRIES {
+            let finalization_res =
+                match finalize_auction(request_id, &self.account, &self.rest_client).await {
+                    Ok(tx) => tx,
+                    Err(_e) => {
+                        // tx failed, possibly due to invalid sequence number
+                        if let Ok(res) = self.rest_client.get_account(self.account.address()).await {
+                            self.account.set_sequence_number(res.inner().sequence_number);
+                        }
+                        curr_try += 1;
+                        continue;
+                    }
+                };
+
+            // wait for tx to be processed
+            let inner = finalization_res.inner();
+            match self
+                .rest_client
+                .wait_for_transaction_by_hash(
+                    inner.hash.into(),
+                    inner.request.expiration_timestamp_secs.into(),
+                    None,
+                    None,
+                )
+                .await
+            {
+                Ok(v) => match v.inner() {
+                    Transaction::UserTransaction(user_tx) if user_tx.info.success => {
+                        finalization_successful = true;
+                    }
+                    _ => curr_try += 1,
+                },
+                Err(_) => curr_try += 1,
+            }
+        }
+
+        if finalization_successful {
+            println!("Request {}: Auction finalized", request_id);
+            self.record(request_id, "done", curr_try + 1, None).await;
+        } else {
+            println!("Request {}: Auction failed to finalize", request_id);
+            self.record(request_id, "failed", curr_try, Some("auction failed to finalize"))
+                .await;
+            self.tracker
+                .track(
+                    request_id,
+                    RequestStatus::Failed,
+                    Some("auction failed to finalize"),
+                )
+                .await;
+        }
+    }
+}
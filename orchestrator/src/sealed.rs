@@ -0,0 +1,137 @@
+use std::str::FromStr;
+
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{error, get, post, web, HttpRequest, HttpResponse, Responder};
+use aptos_sdk::types::account_address::AccountAddress;
+use proxirun_sdk::encryption::SealedPayload;
+use proxirun_sdk::submission::EncryptionKeyRegistration;
+use sqlx::{Pool, Postgres};
+
+use crate::auth::{verify_key_registration, verify_requester};
+use crate::AppState;
+
+// addresses are compared in their canonical form, the events and the workers may pad them differently
+fn canonical_address(address: &str) -> Result<String, actix_web::Error> {
+    Ok(AccountAddress::from_str(address)
+        .map_err(error::ErrorBadRequest)?
+        .to_hex_literal())
+}
+
+pub async fn fetch_sealed_payload(
+    pool: &Pool<Postgres>,
+    request_id: u64,
+) -> Result<Option<SealedPayload>, sqlx::Error> {
+    let sealed: Option<(String, String, String)> = sqlx::query_as(
+        "SELECT ephemeral_key, nonce, ciphertext from sealed_payloads where request_id=$1;",
+    )
+    .bind(request_id as i64)
+    .fetch_optional(pool)
+    .await?;
+
+    Ok(sealed.map(|(ephemeral_key, nonce, ciphertext)| SealedPayload {
+        ephemeral_key,
+        nonce,
+        ciphertext,
+    }))
+}
+
+/// Registers the x25519 key a worker receives sealed payloads with, replacing an older registration.
+#[post("/workers/encryption-key")]
+async fn register_encryption_key(
+    registration: web::Json<EncryptionKeyRegistration>,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    verify_key_registration(&app_state, &registration).await?;
+
+    // a registration replayed after a newer one is ignored
+    let res = sqlx::query(
+        "INSERT into worker_keys (address, encryption_key, registered_at, public_key, signature) \
+         values ($1, $2, $3, $4, $5) \
+         ON CONFLICT (address) DO UPDATE SET encryption_key=$2, registered_at=$3, public_key=$4, \
+         signature=$5, updated_at=now() WHERE worker_keys.registered_at < $3;",
+    )
+    .bind(canonical_address(&registration.address)?)
+    .bind(&registration.encryption_key)
+    .bind(registration.registered_at as i64)
+    .bind(&registration.public_key)
+    .bind(&registration.signature)
+    .execute(&app_state.db_pool)
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    if res.rows_affected() == 0 {
+        return Err(error::ErrorConflict("A newer key is registered for this account"));
+    }
+
+    Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().finish())
+}
+
+/// Signed registration of the x25519 key of the winner of the request, available once the auction is over.
+/// Requesters check it with `proxirun_sdk::encryption::seal_payload_to_winner`.
+#[get("/requests/{id}/encryption-key")]
+async fn winner_encryption_key(
+    id: web::Path<u64>,
+    app_state: web::Data<AppState>,
+) -> impl Responder {
+    let winner = match app_state.tracker.winner(*id).await {
+        Ok(Some(winner)) => canonical_address(&winner)?,
+        Ok(None) => return Err(error::ErrorConflict("The auction has no winner yet")),
+        Err(e) => return Err(error::ErrorInternalServerError(e)),
+    };
+
+    let key: Option<(String, i64, String, String)> = sqlx::query_as(
+        "SELECT encryption_key, registered_at, public_key, signature from worker_keys where address=$1;",
+    )
+    .bind(&winner)
+    .fetch_optional(&app_state.db_pool)
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    match key {
+        Some((encryption_key, registered_at, public_key, signature)) => {
+            Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().json(
+                EncryptionKeyRegistration {
+                    address: winner,
+                    encryption_key,
+                    registered_at: registered_at as u64,
+                    public_key,
+                    signature,
+                },
+            ))
+        }
+        None => Err(error::ErrorNotFound("The winner has no encryption key")),
+    }
+}
+
+/// Stores the payload sealed by the requester to the winner, it can only be uploaded once.
+#[post("/requests/{id}/sealed-payload")]
+async fn upload_sealed_payload(
+    id: web::Path<u64>,
+    body: Bytes,
+    app_state: web::Data<AppState>,
+    req: HttpRequest,
+) -> impl Responder {
+    verify_requester(&app_state, *id, &body, &req).await?;
+    let sealed: SealedPayload = serde_json::from_slice(&body).map_err(error::ErrorBadRequest)?;
+
+    let res = sqlx::query(
+        "INSERT into sealed_payloads (request_id, ephemeral_key, nonce, ciphertext) values ($1, $2, $3, $4) \
+         ON CONFLICT (request_id) DO NOTHING;",
+    )
+    .bind(*id as i64)
+    .bind(&sealed.ephemeral_key)
+    .bind(&sealed.nonce)
+    .bind(&sealed.ciphertext)
+    .execute(&app_state.db_pool)
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    if res.rows_affected() == 0 {
+        return Ok::<HttpResponse, actix_web::Error>(HttpResponse::new(StatusCode::CONFLICT));
+    }
+
+    println!("Request {}: Received sealed payload", *id);
+
+    Ok::<HttpResponse, actix_web::Error>(HttpResponse::Ok().finish())
+}
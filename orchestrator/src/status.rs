@@ -0,0 +1,164 @@
+use std::convert::Infallible;
+use std::time::Duration;
+
+use actix_web::http::StatusCode;
+use actix_web::web::Bytes;
+use actix_web::{get, web, HttpResponse, Responder};
+use serde::Serialize;
+use sqlx::{Pool, Postgres};
+use tokio::sync::broadcast::error::RecvError;
+use tokio::sync::{broadcast, mpsc};
+use tokio::time::{interval_at, Instant};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+
+use crate::lifecycle::{RequestStatus, StatusUpdate};
+use crate::AppState;
+
+// comment lines sent on idle streams, so that proxies do not close them
+const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+#[derive(sqlx::FromRow, Serialize)]
+struct TransitionDb {
+    pub from_status: Option<String>,
+    pub to_status: String,
+    pub detail: Option<String>,
+    pub at_ms: i64,
+}
+
+#[derive(Serialize)]
+struct RequestStatusView {
+    pub request_id: u64,
+    pub status: String,
+    pub winner: Option<String>,
+    pub bid_count: i64,
+    /// Unix times in milliseconds
+    pub created_at_ms: Option<i64>,
+    pub updated_at_ms: i64,
+    pub transitions: Vec<TransitionDb>,
+}
+
+async fn fetch_status(
+    pool: &Pool<Postgres>,
+    request_id: u64,
+) -> Result<Option<RequestStatusView>, sqlx::Error> {
+    let current: Option<(String, Option<String>, i64)> = sqlx::query_as(
+        "SELECT status, winner, (extract(epoch from updated_at) * 1000)::BIGINT from request_status where request_id=$1;",
+    )
+    .bind(request_id as i64)
+    .fetch_optional(pool)
+    .await?;
+    let (status, winner, updated_at_ms) = match current {
+        Some(current) => current,
+        None => return Ok(None),
+    };
+
+    let (bid_count,): (i64,) = sqlx::query_as("SELECT count(*) from bids where request_id=$1;")
+        .bind(request_id as i64)
+        .fetch_one(pool)
+        .await?;
+
+    let transitions = sqlx::query_as::<_, TransitionDb>(
+        "SELECT from_status, to_status, detail, (extract(epoch from created_at) * 1000)::BIGINT as at_ms \
+         from request_transitions where request_id=$1 order by id;",
+    )
+    .bind(request_id as i64)
+    .fetch_all(pool)
+    .await?;
+
+    Ok(Some(RequestStatusView {
+        request_id,
+        status,
+        winner,
+        bid_count,
+        created_at_ms: transitions.first().map(|t| t.at_ms),
+        updated_at_ms,
+        transitions,
+    }))
+}
+
+fn sse_event(name: &str, data: &impl Serialize) -> Bytes {
+    Bytes::from(format!(
+        "event: {}\ndata: {}\n\n",
+        name,
+        serde_json::to_string(data).unwrap()
+    ))
+}
+
+#[get("/requests/{id}/status")]
+async fn request_status(id: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+    match fetch_status(&app_state.db_pool, *id).await {
+        Ok(Some(status)) => HttpResponse::Ok().json(status),
+        Ok(None) => HttpResponse::new(StatusCode::NOT_FOUND),
+        Err(e) => {
+            println!("Request {}: failed to read status: {}", *id, e);
+            HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+fn is_final(status: &str) -> bool {
+    RequestStatus::from_row(status, None).map_or(false, |status| status.is_final())
+}
+
+/// Sends the transitions of a request to an event stream, until the request reaches a
+/// final status or the client goes away.
+async fn forward_updates(
+    request_id: u64,
+    mut updates: broadcast::Receiver<StatusUpdate>,
+    sender: mpsc::Sender<Bytes>,
+) {
+    let mut keepalive = interval_at(Instant::now() + KEEPALIVE_INTERVAL, KEEPALIVE_INTERVAL);
+    loop {
+        let (event, last) = tokio::select! {
+            update = updates.recv() => match update {
+                Ok(update) if update.request_id == request_id => {
+                    (sse_event("transition", &update), is_final(update.status))
+                }
+                // a lagging client skips the updates it missed, the next ones carry the
+                // current status
+                Ok(_) | Err(RecvError::Lagged(_)) => continue,
+                Err(RecvError::Closed) => return,
+            },
+            _ = keepalive.tick() => (Bytes::from_static(b": keepalive\n\n"), false),
+        };
+        if sender.send(event).await.is_err() || last {
+            return;
+        }
+    }
+}
+
+/// Server-Sent Events stream of a request: a `status` event with the current status,
+/// if the request is known, then a `transition` event for each stage change. The stream
+/// ends once the request is committed, failed or expired.
+#[get("/requests/{id}/events")]
+async fn request_events(id: web::Path<u64>, app_state: web::Data<AppState>) -> impl Responder {
+    let request_id = *id;
+
+    // subscribe before reading the status, so that no transition is missed in between
+    let updates = app_state.tracker.subscribe();
+    let current = match fetch_status(&app_state.db_pool, request_id).await {
+        Ok(current) => current,
+        Err(e) => {
+            println!("Request {}: failed to read status: {}", request_id, e);
+            return HttpResponse::new(StatusCode::INTERNAL_SERVER_ERROR);
+        }
+    };
+
+    let (sender, receiver) = mpsc::channel(16);
+    let finished = current.as_ref().map_or(false, |status| is_final(&status.status));
+    if let Some(status) = current {
+        let _ = sender.try_send(sse_event("status", &status));
+    }
+    // dropping the sender of a request already final ends the stream after its status
+    if !finished {
+        tokio::spawn(forward_updates(request_id, updates, sender));
+    }
+
+    let stream = ReceiverStream::new(receiver).map(Ok::<Bytes, Infallible>);
+
+    HttpResponse::Ok()
+        .content_type("text/event-stream")
+        .insert_header(("Cache-Control", "no-cache"))
+        .streaming(stream)
+}
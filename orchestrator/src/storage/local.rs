@@ -0,0 +1,76 @@
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use async_trait::async_trait;
+use proxirun_sdk::submission::content_hash;
+
+use super::{ResultStore, StoreError};
+
+/// Files under a root directory, sharded in two levels of directories named after the
+/// hash of the key, so that no directory grows past a few thousand entries.
+pub struct LocalStore {
+    root: PathBuf,
+    // numbers the temporary files, so that concurrent writes never share one
+    next_tmp: AtomicU64,
+}
+
+impl LocalStore {
+    pub fn new(root: impl AsRef<Path>) -> std::io::Result<Self> {
+        let root = root.as_ref().to_path_buf();
+        std::fs::create_dir_all(&root)?;
+        Ok(LocalStore {
+            root,
+            next_tmp: AtomicU64::new(0),
+        })
+    }
+
+    // `blobs/3f2a..` -> `<root>/ab/cd/blobs_3f2a..`
+    fn path(&self, key: &str) -> PathBuf {
+        let hash = hex::encode(content_hash(key.as_bytes()));
+        self.root
+            .join(&hash[0..2])
+            .join(&hash[2..4])
+            .join(key.replace('/', "_"))
+    }
+}
+
+#[async_trait]
+impl ResultStore for LocalStore {
+    async fn put(&self, key: &str, content: &[u8], _content_type: &str) -> Result<(), StoreError> {
+        let path = self.path(key);
+        if let Some(dir) = path.parent() {
+            tokio::fs::create_dir_all(dir).await?;
+        }
+
+        // readers never see a partially written file
+        let mut tmp_name = path.file_name().unwrap_or_default().to_os_string();
+        tmp_name.push(format!(
+            ".{}.{}.tmp",
+            std::process::id(),
+            self.next_tmp.fetch_add(1, Ordering::Relaxed)
+        ));
+        let tmp_path = path.with_file_name(tmp_name);
+        let written = match tokio::fs::write(&tmp_path, content).await {
+            Ok(()) => tokio::fs::rename(&tmp_path, &path).await,
+            Err(e) => Err(e),
+        };
+        if written.is_err() {
+            let _ = tokio::fs::remove_file(&tmp_path).await;
+        }
+        Ok(written?)
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StoreError> {
+        match tokio::fs::read(self.path(key)).await {
+            Ok(content) => Ok(Some(content)),
+            Err(e) if e.kind() == ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn presigned_url(&self, _key: &str, _expires_in: Duration) -> Result<Option<String>, StoreError> {
+        Ok(None)
+    }
+}
@@ -0,0 +1,68 @@
+pub mod local;
+pub mod s3;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+
+pub use self::local::LocalStore;
+pub use self::s3::S3Store;
+
+pub type StoreError = Box<dyn std::error::Error + Send + Sync>;
+
+/// Where the image and voice results are kept. With a remote backend, every orchestrator
+/// replica reads and writes the same objects.
+#[async_trait]
+pub trait ResultStore: Send + Sync {
+    async fn put(&self, key: &str, content: &[u8], content_type: &str) -> Result<(), StoreError>;
+
+    /// `None` if nothing is stored under `key`
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StoreError>;
+
+    /// URL to download `key` from without going through the orchestrator, valid for `expires_in`.
+    /// `None` if the backend cannot hand out such URLs.
+    async fn presigned_url(&self, key: &str, expires_in: Duration) -> Result<Option<String>, StoreError>;
+}
+
+/// Key of a stored file, from its hex encoded SHA-256
+pub fn blob_key(content_hash: &str) -> String {
+    format!("blobs/{}", content_hash)
+}
+
+/// Key of a variant of the stored file `source_hash`, e.g. `webp-256`
+pub fn variant_key(source_hash: &str, variant: &str) -> String {
+    format!("variants/{}/{}", source_hash, variant)
+}
+
+fn env_var(name: &str) -> Result<String, StoreError> {
+    std::env::var(name).map_err(|_| format!("{} must be set", name).into())
+}
+
+/// Backend selected by `STORAGE_BACKEND`, `local` when it is not set.
+///
+/// - `local`: files under `STORAGE_DIR`, `./uploads` by default
+/// - `s3`: objects in `S3_BUCKET`, at `S3_ENDPOINT` for S3-compatible services such as MinIO,
+///   in `S3_REGION` (`us-east-1` by default), with `S3_ACCESS_KEY` / `S3_SECRET_KEY`
+pub fn from_env() -> Result<Arc<dyn ResultStore>, StoreError> {
+    let backend = std::env::var("STORAGE_BACKEND").unwrap_or_else(|_| "local".to_owned());
+
+    match backend.as_str() {
+        "local" => {
+            let root = std::env::var("STORAGE_DIR").unwrap_or_else(|_| "./uploads".to_owned());
+            Ok(Arc::new(LocalStore::new(root)?))
+        }
+        "s3" => {
+            let region = std::env::var("S3_REGION").unwrap_or_else(|_| "us-east-1".to_owned());
+            let store = S3Store::new(
+                &env_var("S3_BUCKET")?,
+                &region,
+                std::env::var("S3_ENDPOINT").ok(),
+                &env_var("S3_ACCESS_KEY")?,
+                &env_var("S3_SECRET_KEY")?,
+            )?;
+            Ok(Arc::new(store))
+        }
+        other => Err(format!("Unknown storage backend: {}, expected local or s3", other).into()),
+    }
+}
@@ -0,0 +1,67 @@
+use std::time::Duration;
+
+use ::s3::creds::Credentials;
+use ::s3::error::S3Error;
+use ::s3::{Bucket, Region};
+use async_trait::async_trait;
+
+use super::{ResultStore, StoreError};
+
+/// Objects in an S3 bucket, or in any S3-compatible service such as MinIO.
+pub struct S3Store {
+    bucket: Box<Bucket>,
+}
+
+impl S3Store {
+    /// `endpoint` is the URL of an S3-compatible service, addressed with path-style URLs
+    /// since their buckets rarely have DNS names. Without it, the AWS endpoint of `region` is used.
+    pub fn new(
+        bucket: &str,
+        region: &str,
+        endpoint: Option<String>,
+        access_key: &str,
+        secret_key: &str,
+    ) -> Result<Self, StoreError> {
+        let credentials = Credentials::new(Some(access_key), Some(secret_key), None, None, None)?;
+
+        let bucket = match endpoint {
+            Some(endpoint) => Bucket::new(
+                bucket,
+                Region::Custom {
+                    region: region.to_owned(),
+                    endpoint,
+                },
+                credentials,
+            )?
+            .with_path_style(),
+            None => Bucket::new(bucket, region.parse()?, credentials)?,
+        };
+
+        Ok(S3Store { bucket })
+    }
+}
+
+#[async_trait]
+impl ResultStore for S3Store {
+    async fn put(&self, key: &str, content: &[u8], content_type: &str) -> Result<(), StoreError> {
+        self.bucket
+            .put_object_with_content_type(key, content, content_type)
+            .await?;
+        Ok(())
+    }
+
+    async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, StoreError> {
+        match self.bucket.get_object(key).await {
+            Ok(response) => Ok(Some(response.bytes().to_vec())),
+            Err(S3Error::HttpFailWithBody(404, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    async fn presigned_url(&self, key: &str, expires_in: Duration) -> Result<Option<String>, StoreError> {
+        let url = self
+            .bucket
+            .presign_get(key, expires_in.as_secs() as u32, None)?;
+        Ok(Some(url))
+    }
+}
@@ -0,0 +1,102 @@
+use std::fmt;
+use std::process::Stdio;
+
+use tokio::io::AsyncWriteExt;
+use tokio::process::Command;
+
+#[derive(Debug)]
+pub enum TranscodeError {
+    /// ffmpeg could not be started, e.g. it is not installed
+    Spawn(std::io::Error),
+    /// ffmpeg rejected the input or the encoder is missing, with its error output
+    Failed(String),
+}
+
+impl fmt::Display for TranscodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TranscodeError::Spawn(e) => write!(f, "Failed to start ffmpeg: {}", e),
+            TranscodeError::Failed(e) => write!(f, "Transcoding failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for TranscodeError {}
+
+/// Formats the voice results can be transcoded to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AudioFormat {
+    Opus,
+    Mp3,
+    Flac,
+}
+
+impl AudioFormat {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "opus" => Some(AudioFormat::Opus),
+            "mp3" => Some(AudioFormat::Mp3),
+            "flac" => Some(AudioFormat::Flac),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AudioFormat::Opus => "opus",
+            AudioFormat::Mp3 => "mp3",
+            AudioFormat::Flac => "flac",
+        }
+    }
+
+    pub fn mime(&self) -> &'static str {
+        match self {
+            AudioFormat::Opus => "audio/ogg",
+            AudioFormat::Mp3 => "audio/mpeg",
+            AudioFormat::Flac => "audio/flac",
+        }
+    }
+
+    fn encoder_args(&self) -> &'static [&'static str] {
+        match self {
+            AudioFormat::Opus => &["-c:a", "libopus", "-b:a", "64k", "-f", "ogg"],
+            AudioFormat::Mp3 => &["-c:a", "libmp3lame", "-q:a", "2", "-f", "mp3"],
+            AudioFormat::Flac => &["-c:a", "flac", "-f", "flac"],
+        }
+    }
+}
+
+/// Transcodes the audio file `source` with ffmpeg, `FFMPEG_PATH` or `ffmpeg` from the `PATH`.
+pub async fn transcode(source: Vec<u8>, format: AudioFormat) -> Result<Vec<u8>, TranscodeError> {
+    let ffmpeg = std::env::var("FFMPEG_PATH").unwrap_or_else(|_| "ffmpeg".to_owned());
+
+    // bitexact keeps the output identical across runs, so that the cached variants have stable hashes
+    let mut child = Command::new(ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-i", "pipe:0", "-vn", "-map_metadata", "-1"])
+        .args(["-fflags", "+bitexact", "-flags:a", "+bitexact"])
+        .args(format.encoder_args())
+        .arg("pipe:1")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(TranscodeError::Spawn)?;
+
+    // written concurrently with the read of the output, ffmpeg blocks when its pipes are full
+    let mut stdin = child.stdin.take().expect("stdin is piped");
+    let writer = tokio::spawn(async move {
+        // a failed write shows in the exit status of ffmpeg
+        let _ = stdin.write_all(&source).await;
+    });
+
+    let output = child.wait_with_output().await.map_err(TranscodeError::Spawn)?;
+    let _ = writer.await;
+
+    if !output.status.success() {
+        return Err(TranscodeError::Failed(
+            String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+        ));
+    }
+    Ok(output.stdout)
+}
@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::io::Cursor;
+use std::sync::{Arc, Mutex};
+
+use actix_web::{error, web};
+use image::codecs::jpeg::JpegEncoder;
+use image::codecs::webp::WebPEncoder;
+use image::imageops::FilterType;
+use image::{DynamicImage, ImageError, ImageFormat};
+use proxirun_sdk::submission::content_hash;
+use serde::Deserialize;
+use sqlx::{Pool, Postgres};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+
+use crate::outputs::OutputDb;
+use crate::storage::variant_key;
+use crate::transcode::{transcode, AudioFormat};
+use crate::AppState;
+
+/// Widths the images can be resized to, a closed list so that the cache stays bounded
+pub const VARIANT_WIDTHS: [u32; 4] = [128, 256, 512, 1024];
+
+const JPEG_QUALITY: u8 = 85;
+
+/// `?format=webp&width=256` or `?format=opus` of `/output/{id}`
+#[derive(Debug, Deserialize)]
+pub struct VariantQuery {
+    pub format: Option<String>,
+    pub width: Option<u32>,
+}
+
+impl VariantQuery {
+    /// Whether the output itself is asked for
+    pub fn is_original(&self) -> bool {
+        self.format.is_none() && self.width.is_none()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VariantFormat {
+    Jpeg,
+    Png,
+    WebP,
+}
+
+impl VariantFormat {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "jpeg" | "jpg" => Some(VariantFormat::Jpeg),
+            "png" => Some(VariantFormat::Png),
+            "webp" => Some(VariantFormat::WebP),
+            _ => None,
+        }
+    }
+
+    fn from_mime(mime: &str) -> Option<Self> {
+        match mime {
+            "image/jpeg" => Some(VariantFormat::Jpeg),
+            "image/png" => Some(VariantFormat::Png),
+            "image/webp" => Some(VariantFormat::WebP),
+            _ => None,
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            VariantFormat::Jpeg => "jpeg",
+            VariantFormat::Png => "png",
+            VariantFormat::WebP => "webp",
+        }
+    }
+
+    fn mime(&self) -> &'static str {
+        match self {
+            VariantFormat::Jpeg => "image/jpeg",
+            VariantFormat::Png => "image/png",
+            VariantFormat::WebP => "image/webp",
+        }
+    }
+}
+
+/// An image output converted to `format` and, if `width` is set, resized to fit it.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImageVariant {
+    pub format: VariantFormat,
+    pub width: Option<u32>,
+}
+
+impl ImageVariant {
+    fn from_query(query: &VariantQuery, source_format: VariantFormat) -> Result<Self, actix_web::Error> {
+        let format = match &query.format {
+            Some(name) => VariantFormat::from_name(name)
+                .ok_or(error::ErrorBadRequest("Unsupported format, expected jpeg, png or webp"))?,
+            None => source_format,
+        };
+        if let Some(width) = query.width {
+            if !VARIANT_WIDTHS.contains(&width) {
+                return Err(error::ErrorBadRequest(format!(
+                    "Unsupported width, expected one of {:?}",
+                    VARIANT_WIDTHS
+                )));
+            }
+        }
+
+        Ok(ImageVariant {
+            format,
+            width: query.width,
+        })
+    }
+
+    // `webp-256`, `png-full`
+    fn name(&self) -> String {
+        match self.width {
+            Some(width) => format!("{}-{}", self.format.name(), width),
+            None => format!("{}-full", self.format.name()),
+        }
+    }
+
+    /// Converts and resizes `source`, images narrower than `width` are not upscaled.
+    fn render(&self, source: &[u8]) -> Result<Vec<u8>, ImageError> {
+        let mut image = image::load_from_memory(source)?;
+        if let Some(width) = self.width {
+            if width < image.width() {
+                image = image.resize(width, u32::MAX, FilterType::Lanczos3);
+            }
+        }
+        // the encoders only take 8 bits per channel
+        let image = if image.color().has_alpha() && self.format != VariantFormat::Jpeg {
+            DynamicImage::ImageRgba8(image.to_rgba8())
+        } else {
+            DynamicImage::ImageRgb8(image.to_rgb8())
+        };
+
+        let mut out = Cursor::new(vec![]);
+        match self.format {
+            VariantFormat::Jpeg => {
+                image.write_with_encoder(JpegEncoder::new_with_quality(&mut out, JPEG_QUALITY))?
+            }
+            VariantFormat::Png => image.write_to(&mut out, ImageFormat::Png)?,
+            VariantFormat::WebP => image.write_with_encoder(WebPEncoder::new_lossless(&mut out))?,
+        }
+        Ok(out.into_inner())
+    }
+}
+
+/// Variants created as soon as an image is submitted, the ones frontends ask for
+const PREGENERATED: [Variant; 3] = [
+    Variant::Image(ImageVariant {
+        format: VariantFormat::WebP,
+        width: None,
+    }),
+    Variant::Image(ImageVariant {
+        format: VariantFormat::WebP,
+        width: Some(256),
+    }),
+    Variant::Image(ImageVariant {
+        format: VariantFormat::Png,
+        width: Some(256),
+    }),
+];
+
+/// A derived version of an output: a converted image, or a transcoded voice.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Variant {
+    Image(ImageVariant),
+    Audio(AudioFormat),
+}
+
+impl Variant {
+    /// Variant asked by `query` for an output of `content_type`, `None` for the output itself.
+    pub fn from_query(query: &VariantQuery, content_type: &str) -> Result<Option<Self>, actix_web::Error> {
+        if query.is_original() {
+            return Ok(None);
+        }
+
+        if let Some(source_format) = VariantFormat::from_mime(content_type) {
+            return Ok(Some(Variant::Image(ImageVariant::from_query(query, source_format)?)));
+        }
+        if !content_type.starts_with("audio/") {
+            return Err(error::ErrorBadRequest("This output has no variants"));
+        }
+        if query.width.is_some() {
+            return Err(error::ErrorBadRequest("Voices cannot be resized"));
+        }
+        let format = query
+            .format
+            .as_deref()
+            .and_then(AudioFormat::from_name)
+            .ok_or(error::ErrorBadRequest("Unsupported format, expected opus, mp3 or flac"))?;
+
+        Ok(Some(Variant::Audio(format)))
+    }
+
+    // `webp-256`, `png-full`, `opus`
+    fn name(&self) -> String {
+        match self {
+            Variant::Image(variant) => variant.name(),
+            Variant::Audio(format) => format.name().to_owned(),
+        }
+    }
+
+    fn mime(&self) -> &'static str {
+        match self {
+            Variant::Image(variant) => variant.format.mime(),
+            Variant::Audio(format) => format.mime(),
+        }
+    }
+
+    async fn render(self, source: Vec<u8>) -> Result<Vec<u8>, actix_web::Error> {
+        match self {
+            Variant::Image(variant) => web::block(move || variant.render(&source))
+                .await?
+                .map_err(error::ErrorInternalServerError),
+            Variant::Audio(format) => transcode(source, format)
+                .await
+                .map_err(error::ErrorInternalServerError),
+        }
+    }
+}
+
+/// Variants being rendered, so that a variant asked for by concurrent readers is rendered once.
+#[derive(Clone, Default)]
+pub struct RenderLocks {
+    // `<source hash>/<variant>` -> lock held while the variant is rendered
+    locks: Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>,
+}
+
+impl RenderLocks {
+    async fn lock(&self, source_hash: &str, variant: &str) -> RenderGuard {
+        let key = format!("{}/{}", source_hash, variant);
+        let lock = self.locks.lock().unwrap().entry(key.clone()).or_default().clone();
+        let guard = lock.lock_owned().await;
+        RenderGuard {
+            locks: self.locks.clone(),
+            key,
+            _guard: guard,
+        }
+    }
+}
+
+struct RenderGuard {
+    locks: Arc<Mutex<HashMap<String, Arc<AsyncMutex<()>>>>>,
+    key: String,
+    _guard: OwnedMutexGuard<()>,
+}
+
+impl Drop for RenderGuard {
+    fn drop(&mut self) {
+        let mut locks = self.locks.lock().unwrap();
+        // the map and this guard hold the last references when no reader waits for the lock
+        if locks.get(&self.key).map_or(false, |lock| Arc::strong_count(lock) <= 2) {
+            locks.remove(&self.key);
+        }
+    }
+}
+
+async fn fetch_variant(
+    pool: &Pool<Postgres>,
+    source_hash: &str,
+    variant: &str,
+) -> Result<Option<OutputDb>, sqlx::Error> {
+    sqlx::query_as::<_, OutputDb>(
+        "SELECT content_type, content_hash, location from variants where source_hash=$1 and variant=$2;",
+    )
+    .bind(source_hash)
+    .bind(variant)
+    .fetch_optional(pool)
+    .await
+}
+
+/// Renders the variant of `source` and caches it in the result store.
+async fn create_variant(
+    app_state: &AppState,
+    source_hash: &str,
+    source: Vec<u8>,
+    variant: Variant,
+) -> Result<OutputDb, actix_web::Error> {
+    let content = variant.render(source).await?;
+
+    let name = variant.name();
+    let stored = OutputDb {
+        content_type: variant.mime().to_owned(),
+        content_hash: hex::encode(content_hash(&content)),
+        location: variant_key(source_hash, &name),
+    };
+    app_state
+        .store
+        .put(&stored.location, &content, &stored.content_type)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+
+    // the variants are deterministic, a concurrent render stored the same bytes
+    sqlx::query(
+        "INSERT into variants (source_hash, variant, location, content_type, content_hash, size) \
+         values ($1, $2, $3, $4, $5, $6) ON CONFLICT (source_hash, variant) DO NOTHING;",
+    )
+    .bind(source_hash)
+    .bind(&name)
+    .bind(&stored.location)
+    .bind(&stored.content_type)
+    .bind(&stored.content_hash)
+    .bind(content.len() as i64)
+    .execute(&app_state.db_pool)
+    .await
+    .map_err(error::ErrorInternalServerError)?;
+
+    Ok(stored)
+}
+
+/// The variant of the output `source`, rendered on first use.
+pub async fn ensure_variant(
+    app_state: &AppState,
+    source: &OutputDb,
+    variant: Variant,
+) -> Result<OutputDb, actix_web::Error> {
+    let name = variant.name();
+    let cached = fetch_variant(&app_state.db_pool, &source.content_hash, &name)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    if let Some(cached) = cached {
+        return Ok(cached);
+    }
+
+    // the readers that waited find the variant rendered by the first one
+    let _guard = app_state.render_locks.lock(&source.content_hash, &name).await;
+    let cached = fetch_variant(&app_state.db_pool, &source.content_hash, &name)
+        .await
+        .map_err(error::ErrorInternalServerError)?;
+    if let Some(cached) = cached {
+        return Ok(cached);
+    }
+
+    let content = match app_state.store.get(&source.location).await {
+        Ok(Some(content)) => content,
+        Ok(None) => return Err(error::ErrorNotFound("Missing output")),
+        Err(e) => return Err(error::ErrorInternalServerError(e)),
+    };
+    create_variant(app_state, &source.content_hash, content, variant).await
+}
+
+/// Renders the common variants of a newly submitted image, so that the first readers do not wait.
+pub async fn pregenerate_variants(app_state: &AppState, id: u64, source_hash: &str, source: &[u8]) {
+    for variant in PREGENERATED {
+        let _guard = app_state.render_locks.lock(source_hash, &variant.name()).await;
+        match fetch_variant(&app_state.db_pool, source_hash, &variant.name()).await {
+            Ok(None) => (),
+            // an identical image was submitted before
+            Ok(Some(_)) => continue,
+            Err(e) => {
+                println!("Request {}: failed to read variants: {}", id, e);
+                return;
+            }
+        }
+
+        if let Err(e) = create_variant(app_state, source_hash, source.to_vec(), variant).await {
+            println!("Request {}: failed to render {} variant: {}", id, variant.name(), e);
+        }
+    }
+}
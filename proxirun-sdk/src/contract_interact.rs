@@ -1,12 +1,15 @@
+use std::str::FromStr;
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use aptos_sdk::{
+    crypto::ed25519::Ed25519Signature,
     move_types::identifier::Identifier,
-    rest_client::{error::RestError, Client, PendingTransaction, Response},
+    rest_client::{error::RestError, Client, PendingTransaction, Response, UserTransaction},
     transaction_builder::TransactionBuilder,
     types::{
+        account_address::AccountAddress,
         chain_id::ChainId,
-        transaction::{EntryFunction, TransactionPayload},
+        transaction::{EntryFunction, SignedTransaction, TransactionPayload},
         LocalAccount,
     },
 };
@@ -79,6 +82,136 @@ pub async fn finalize_auction(
     return client.submit(&signed_txn).await;
 }
 
+/// Calls the entry function `function` of the contract, whose only argument is the request id.
+/// For the entry points that depend on the deployment, e.g. a refund after a missed deadline.
+pub async fn call_request_function(
+    function: Identifier,
+    request_id: u64,
+    account: &LocalAccount,
+    client: &Client,
+) -> Result<Response<PendingTransaction>, RestError> {
+    let chain_id = client.get_index().await?.into_inner();
+
+    let builder = TransactionBuilder::new(
+        TransactionPayload::EntryFunction(EntryFunction::new(
+            CONTRACT_MODULE.to_owned(),
+            function,
+            vec![],
+            vec![bcs::to_bytes(&request_id).unwrap()],
+        )),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + TIME_OUT,
+        ChainId::new(chain_id.chain_id),
+    )
+    .gas_unit_price(100)
+    .max_gas_amount(1_000)
+    .sender(account.address())
+    .sequence_number(account.sequence_number());
+
+    let signed_txn = account.sign_with_transaction_builder(builder);
+    return client.submit(&signed_txn).await;
+}
+
+/// Calls the entry function `function` of the contract with the request id and an account,
+/// e.g. to hand a request whose winner missed its deadline to another bidder.
+pub async fn call_request_account_function(
+    function: Identifier,
+    request_id: u64,
+    account_arg: AccountAddress,
+    account: &LocalAccount,
+    client: &Client,
+) -> Result<Response<PendingTransaction>, RestError> {
+    let chain_id = client.get_index().await?.into_inner();
+
+    let builder = TransactionBuilder::new(
+        TransactionPayload::EntryFunction(EntryFunction::new(
+            CONTRACT_MODULE.to_owned(),
+            function,
+            vec![],
+            vec![
+                bcs::to_bytes(&request_id).unwrap(),
+                bcs::to_bytes(&account_arg).unwrap(),
+            ],
+        )),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + TIME_OUT,
+        ChainId::new(chain_id.chain_id),
+    )
+    .gas_unit_price(100)
+    .max_gas_amount(1_000)
+    .sender(account.address())
+    .sequence_number(account.sequence_number());
+
+    let signed_txn = account.sign_with_transaction_builder(builder);
+    return client.submit(&signed_txn).await;
+}
+
+/// Simulates `finalize_auction` without submitting it: the transaction fails if the
+/// auction cannot be finalized, e.g. because it already was.
+pub async fn simulate_finalize_auction(
+    request_id: u64,
+    account: &LocalAccount,
+    client: &Client,
+) -> Result<Response<Vec<UserTransaction>>, RestError> {
+    let chain_id = client.get_index().await?.into_inner();
+
+    let raw_txn = TransactionBuilder::new(
+        TransactionPayload::EntryFunction(EntryFunction::new(
+            CONTRACT_MODULE.to_owned(),
+            Identifier::new("finalize_auction").unwrap(),
+            vec![],
+            vec![bcs::to_bytes(&request_id).unwrap()],
+        )),
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + TIME_OUT,
+        ChainId::new(chain_id.chain_id),
+    )
+    .gas_unit_price(100)
+    .max_gas_amount(1_000)
+    .sender(account.address())
+    .sequence_number(account.sequence_number())
+    .build();
+
+    // the fullnode refuses to simulate transactions with a valid signature
+    let txn = SignedTransaction::new(
+        raw_txn,
+        account.public_key().clone(),
+        Ed25519Signature::dummy_signature(),
+    );
+    client.simulate(&txn).await
+}
+
+/// Abort code of a transaction aborted by the contract, from its VM status such as
+/// `Move abort in 0x1::proxirun: E_CLOSED(0x3): ...` or `Move abort in 0x1::proxirun: 0x3`.
+/// `None` for any other failure, including aborts raised by another module.
+pub fn contract_abort_code(vm_status: &str) -> Option<u64> {
+    let rest = vm_status.strip_prefix("Move abort in ")?;
+    let (module, rest) = rest.split_once(": ")?;
+    let (address, name) = module.split_once("::")?;
+    if AccountAddress::from_str(address).ok()? != CONTRACT_MODULE.address
+        || name != CONTRACT_MODULE.name.as_str()
+    {
+        return None;
+    }
+
+    // the code comes alone, or after the name of the error constant
+    let code = rest.split(':').next()?.trim();
+    let code = match code.split_once('(') {
+        Some((_, code)) => code.trim_end_matches(')'),
+        None => code,
+    };
+    u64::from_str_radix(code.strip_prefix("0x")?, 16).ok()
+}
+
 pub async fn commit(
     request_id: u64,
     account: &LocalAccount,
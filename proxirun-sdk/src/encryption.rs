@@ -0,0 +1,198 @@
+use std::fmt;
+use std::str::FromStr;
+
+use aes_gcm::aead::{Aead, Payload};
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use aptos_sdk::rest_client::Client;
+use aptos_sdk::types::account_address::AccountAddress;
+use aptos_sdk::types::transaction::authenticator::AuthenticationKey;
+use hkdf::Hkdf;
+use rand::rngs::OsRng;
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use x25519_dalek::{EphemeralSecret, PublicKey, StaticSecret};
+
+use crate::orchestrator::TaskPayload;
+use crate::submission::{verify_encryption_key, EncryptionKeyRegistration};
+
+// binds the derived key to its use
+const KEY_DERIVATION_INFO: &[u8] = b"PROXIRUN::SEALED_PAYLOAD";
+
+#[derive(Debug)]
+pub enum EncryptionError {
+    /// Key, nonce or ciphertext that is not valid hex or has the wrong length
+    Malformed(String),
+    /// The payload was not sealed to this key or was tampered with
+    Decryption,
+    Serialization(serde_json::Error),
+    /// The registration handed out by the orchestrator is not signed by the winner's account
+    UntrustedKey(String),
+}
+
+impl fmt::Display for EncryptionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EncryptionError::Malformed(e) => write!(f, "Malformed sealed payload: {}", e),
+            EncryptionError::Decryption => write!(f, "Failed to decrypt sealed payload"),
+            EncryptionError::Serialization(e) => write!(f, "Invalid task payload: {}", e),
+            EncryptionError::UntrustedKey(e) => write!(f, "Untrusted encryption key: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for EncryptionError {}
+
+/// A `TaskPayload` encrypted to the x25519 key of a worker, with an ephemeral key
+/// exchange and AES-256-GCM. All fields are hex encoded.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SealedPayload {
+    pub ephemeral_key: String,
+    pub nonce: String,
+    pub ciphertext: String,
+}
+
+/// What `/request-payload/{id}` returns: the payload in clear, or sealed to the winner.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum PayloadEnvelope {
+    Sealed(SealedPayload),
+    Plain(TaskPayload),
+}
+
+fn decode_32(value: &str) -> Result<[u8; 32], EncryptionError> {
+    let bytes = hex::decode(value).map_err(|e| EncryptionError::Malformed(e.to_string()))?;
+    bytes
+        .try_into()
+        .map_err(|_| EncryptionError::Malformed("expected 32 bytes".to_owned()))
+}
+
+// the request id is part of the derived key and of the authenticated data,
+// a payload sealed for a request cannot be replayed for another
+fn cipher(
+    shared_secret: &[u8; 32],
+    ephemeral_key: &PublicKey,
+    worker_key: &PublicKey,
+    request_id: u64,
+) -> Aes256Gcm {
+    let mut info = KEY_DERIVATION_INFO.to_vec();
+    info.extend_from_slice(&request_id.to_le_bytes());
+    info.extend_from_slice(ephemeral_key.as_bytes());
+    info.extend_from_slice(worker_key.as_bytes());
+
+    let mut key = [0u8; 32];
+    Hkdf::<Sha256>::new(None, shared_secret)
+        .expand(&info, &mut key)
+        .expect("32 bytes is a valid HKDF-SHA256 output length");
+
+    Aes256Gcm::new_from_slice(&key).expect("AES-256 keys are 32 bytes")
+}
+
+/// Encrypts `payload` to the hex encoded x25519 key that the winner of `request_id` registered.
+pub fn seal_payload(
+    worker_key: &str,
+    request_id: u64,
+    payload: &TaskPayload,
+) -> Result<SealedPayload, EncryptionError> {
+    let worker_key = PublicKey::from(decode_32(worker_key)?);
+    let ephemeral_secret = EphemeralSecret::new(OsRng);
+    let ephemeral_key = PublicKey::from(&ephemeral_secret);
+    let shared_secret = ephemeral_secret.diffie_hellman(&worker_key);
+
+    let mut nonce = [0u8; 12];
+    OsRng.fill_bytes(&mut nonce);
+
+    let plaintext = serde_json::to_vec(payload).map_err(EncryptionError::Serialization)?;
+    let ciphertext = cipher(shared_secret.as_bytes(), &ephemeral_key, &worker_key, request_id)
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &plaintext,
+                aad: &request_id.to_le_bytes(),
+            },
+        )
+        .map_err(|_| EncryptionError::Malformed("payload too large".to_owned()))?;
+
+    Ok(SealedPayload {
+        ephemeral_key: hex::encode(ephemeral_key.as_bytes()),
+        nonce: hex::encode(nonce),
+        ciphertext: hex::encode(ciphertext),
+    })
+}
+
+/// Encrypts `payload` to the key of the winner of `request_id`, once the signature of its
+/// registration is checked against the on-chain authentication key of `winner`. `winner` must
+/// be read from the `OnBidWon` event, the orchestrator is not trusted with the key nor the winner.
+pub async fn seal_payload_to_winner(
+    registration: &EncryptionKeyRegistration,
+    winner: &str,
+    request_id: u64,
+    payload: &TaskPayload,
+    client: &Client,
+) -> Result<SealedPayload, EncryptionError> {
+    let address = |address: &str| {
+        AccountAddress::from_str(address).map_err(|e| EncryptionError::UntrustedKey(e.to_string()))
+    };
+    let winner = address(winner)?;
+    if address(&registration.address)? != winner {
+        return Err(EncryptionError::UntrustedKey(
+            "registered for another account".to_owned(),
+        ));
+    }
+
+    let public_key = verify_encryption_key(registration)
+        .map_err(|e| EncryptionError::UntrustedKey(e.to_string()))?;
+    let account = client
+        .get_account(winner)
+        .await
+        .map_err(|e| EncryptionError::UntrustedKey(e.to_string()))?;
+    if account.inner().authentication_key != AuthenticationKey::ed25519(&public_key) {
+        return Err(EncryptionError::UntrustedKey(
+            "not signed by the winner's key".to_owned(),
+        ));
+    }
+
+    seal_payload(&registration.encryption_key, request_id, payload)
+}
+
+/// x25519 key pair of a worker, the private half never leaves the worker.
+pub struct EncryptionKey {
+    secret: StaticSecret,
+}
+
+impl EncryptionKey {
+    pub fn generate() -> Self {
+        EncryptionKey {
+            secret: StaticSecret::new(OsRng),
+        }
+    }
+
+    /// Hex encoded public key, registered with the orchestrator for requesters to seal payloads
+    pub fn public_key(&self) -> String {
+        hex::encode(PublicKey::from(&self.secret).as_bytes())
+    }
+
+    pub fn open(&self, request_id: u64, sealed: &SealedPayload) -> Result<TaskPayload, EncryptionError> {
+        let ephemeral_key = PublicKey::from(decode_32(&sealed.ephemeral_key)?);
+        let nonce = hex::decode(&sealed.nonce).map_err(|e| EncryptionError::Malformed(e.to_string()))?;
+        if nonce.len() != 12 {
+            return Err(EncryptionError::Malformed("expected a 12 bytes nonce".to_owned()));
+        }
+        let ciphertext =
+            hex::decode(&sealed.ciphertext).map_err(|e| EncryptionError::Malformed(e.to_string()))?;
+
+        let shared_secret = self.secret.diffie_hellman(&ephemeral_key);
+        let worker_key = PublicKey::from(&self.secret);
+        let plaintext = cipher(shared_secret.as_bytes(), &ephemeral_key, &worker_key, request_id)
+            .decrypt(
+                Nonce::from_slice(&nonce),
+                Payload {
+                    msg: &ciphertext,
+                    aad: &request_id.to_le_bytes(),
+                },
+            )
+            .map_err(|_| EncryptionError::Decryption)?;
+
+        serde_json::from_slice(&plaintext).map_err(EncryptionError::Serialization)
+    }
+}
@@ -1,10 +1,17 @@
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 
 mod string_to_u64 {
-    use serde::Deserializer;
+    use serde::{Deserializer, Serializer};
     use std::str::FromStr;
 
     use super::*;
+
+    pub fn serialize<S>(value: &u64, serializer: S) -> Result<S::Ok, S::Error>
+    where
+        S: Serializer,
+    {
+        serializer.serialize_str(&value.to_string())
+    }
     
     pub fn deserialize<'de, D>(deserializer: D) -> Result<u64, D::Error>
     where
@@ -16,7 +23,7 @@ mod string_to_u64 {
 }
 
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OnNewWorkRequest {
     #[serde(with = "string_to_u64")]
     pub request_id: u64,
@@ -27,13 +34,13 @@ pub struct OnNewWorkRequest {
     pub time_limit: u64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OnWorkRequestCompleted {
     #[serde(with = "string_to_u64")]
     pub request_id: u64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OnNewWorkRequestBid {
     #[serde(with = "string_to_u64")]
     pub request_id: u64,
@@ -42,7 +49,7 @@ pub struct OnNewWorkRequestBid {
     pub price: u64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OnBidWon {
     #[serde(with = "string_to_u64")]
     pub request_id: u64,
@@ -51,17 +58,26 @@ pub struct OnBidWon {
     pub bid_price: u64,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct OnAuctionFailure {
     #[serde(with = "string_to_u64")]
     pub request_id: u64,
 }
 
-#[derive(Debug)]
+/// Event emitted by the contract that this SDK version does not know about,
+/// e.g. after a contract upgrade. `data` holds the raw JSON fields.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UnknownEvent {
+    pub name: String,
+    pub data: String,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum ContractEvent {
     OnNewWorkRequest(OnNewWorkRequest),
     OnWorkRequestCompleted(OnWorkRequestCompleted),
     OnNewWorkRequestBid(OnNewWorkRequestBid),
     OnBidWon(OnBidWon),
     OnAuctionFailure(OnAuctionFailure),
+    Unknown(UnknownEvent),
 }
\ No newline at end of file
@@ -1,4 +1,7 @@
 pub mod events;
 pub mod orchestrator;
+pub mod receipt;
 pub mod contract_interact;
-pub mod constants;
\ No newline at end of file
+pub mod constants;
+pub mod encryption;
+pub mod submission;
\ No newline at end of file
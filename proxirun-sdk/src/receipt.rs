@@ -0,0 +1,70 @@
+use aptos_sdk::crypto::ed25519::Ed25519PublicKey;
+use aptos_sdk::types::LocalAccount;
+use serde::{Deserialize, Serialize};
+
+use crate::submission::{sign_message, verify_message, SubmissionError, SubmissionSignature};
+
+/// Hex encoded SHA-256 of a delivered output, as returned by `/output/{id}`
+pub const CONTENT_HASH_HEADER: &str = "X-ProxiRun-Content-Hash";
+
+const RECEIPT_DOMAIN: &[u8] = b"PROXIRUN::DELIVERY_RECEIPT";
+
+/// Statement signed by the orchestrator that the output with `content_hash` was delivered
+/// for `request_id` and committed on chain in `commit_transaction`.
+/// The `commit` entry function only takes the request id, the receipt binds the output to it.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryReceipt {
+    pub request_id: u64,
+    pub content_hash: String,
+    pub commit_transaction: String,
+    pub orchestrator: String,
+    pub public_key: String,
+    pub signature: String,
+}
+
+fn receipt_message(request_id: u64, content_hash: &str, commit_transaction: &str) -> Vec<u8> {
+    let mut message = RECEIPT_DOMAIN.to_vec();
+    message.extend_from_slice(&request_id.to_le_bytes());
+    message.extend_from_slice(content_hash.as_bytes());
+    message.extend_from_slice(commit_transaction.as_bytes());
+    message
+}
+
+pub fn sign_receipt(
+    account: &LocalAccount,
+    request_id: u64,
+    content_hash: &[u8; 32],
+    commit_transaction: &str,
+) -> DeliveryReceipt {
+    let content_hash = hex::encode(content_hash);
+    let signature = sign_message(
+        account,
+        &receipt_message(request_id, &content_hash, commit_transaction),
+    );
+
+    DeliveryReceipt {
+        request_id,
+        content_hash,
+        commit_transaction: commit_transaction.to_owned(),
+        orchestrator: account.address().to_hex_literal(),
+        public_key: signature.public_key,
+        signature: signature.signature,
+    }
+}
+
+/// Checks the signature of the receipt, returns the public key that signed it.
+/// The caller still has to check that the key is the orchestrator's and that the
+/// SHA-256 of the output it received matches `content_hash`.
+pub fn verify_receipt(receipt: &DeliveryReceipt) -> Result<Ed25519PublicKey, SubmissionError> {
+    verify_message(
+        &receipt_message(
+            receipt.request_id,
+            &receipt.content_hash,
+            &receipt.commit_transaction,
+        ),
+        &SubmissionSignature {
+            public_key: receipt.public_key.to_owned(),
+            signature: receipt.signature.to_owned(),
+        },
+    )
+}
@@ -0,0 +1,205 @@
+use std::fmt;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use aptos_sdk::crypto::ed25519::{Ed25519PublicKey, Ed25519Signature};
+use aptos_sdk::crypto::{Signature, SigningKey, ValidCryptoMaterial};
+use aptos_sdk::types::account_address::AccountAddress;
+use aptos_sdk::types::transaction::authenticator::AuthenticationKey;
+use aptos_sdk::types::LocalAccount;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// Hex encoded Ed25519 public key of the worker signing a request
+pub const PUBLIC_KEY_HEADER: &str = "X-ProxiRun-Public-Key";
+/// Hex encoded Ed25519 signature of `submission_message` or `payload_access_message`
+pub const SIGNATURE_HEADER: &str = "X-ProxiRun-Signature";
+/// Nonce of the challenge answered to read a task payload
+pub const NONCE_HEADER: &str = "X-ProxiRun-Nonce";
+
+// keep the signatures of a kind from being valid for any other message
+const SUBMISSION_DOMAIN: &[u8] = b"PROXIRUN::SUBMISSION";
+const PAYLOAD_ACCESS_DOMAIN: &[u8] = b"PROXIRUN::PAYLOAD_ACCESS";
+const ENCRYPTION_KEY_DOMAIN: &[u8] = b"PROXIRUN::ENCRYPTION_KEY";
+const SEALED_PAYLOAD_DOMAIN: &[u8] = b"PROXIRUN::SEALED_PAYLOAD_UPLOAD";
+
+#[derive(Debug)]
+pub enum SubmissionError {
+    /// Public key or signature that is not valid hex or Ed25519 material
+    Malformed(String),
+    InvalidSignature,
+}
+
+impl fmt::Display for SubmissionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SubmissionError::Malformed(e) => write!(f, "Malformed signature: {}", e),
+            SubmissionError::InvalidSignature => write!(f, "Invalid signature"),
+        }
+    }
+}
+
+impl std::error::Error for SubmissionError {}
+
+/// Headers proving which account produced a submission or answered a challenge.
+#[derive(Debug, Clone)]
+pub struct SubmissionSignature {
+    pub public_key: String,
+    pub signature: String,
+}
+
+/// One-time challenge handed out by the orchestrator before it serves a task payload.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayloadChallenge {
+    pub nonce: String,
+    pub expires_in_secs: u64,
+}
+
+/// x25519 key a worker registers with the orchestrator, signed with its account key.
+/// The orchestrator hands it to requesters as is, so that they check the signature themselves.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EncryptionKeyRegistration {
+    pub address: String,
+    pub encryption_key: String,
+    /// Unix time of the registration in milliseconds, signed so that an old registration cannot be replayed
+    pub registered_at: u64,
+    pub public_key: String,
+    pub signature: String,
+}
+
+pub(crate) fn sign_message(account: &LocalAccount, message: &[u8]) -> SubmissionSignature {
+    let signature = account.private_key().sign_arbitrary_message(message);
+
+    SubmissionSignature {
+        public_key: hex::encode(account.public_key().to_bytes()),
+        signature: hex::encode(signature.to_bytes()),
+    }
+}
+
+pub(crate) fn verify_message(
+    message: &[u8],
+    signature: &SubmissionSignature,
+) -> Result<Ed25519PublicKey, SubmissionError> {
+    let public_key_bytes =
+        hex::decode(&signature.public_key).map_err(|e| SubmissionError::Malformed(e.to_string()))?;
+    let signature_bytes =
+        hex::decode(&signature.signature).map_err(|e| SubmissionError::Malformed(e.to_string()))?;
+    let public_key = Ed25519PublicKey::try_from(public_key_bytes.as_slice())
+        .map_err(|e| SubmissionError::Malformed(e.to_string()))?;
+    let signature = Ed25519Signature::try_from(signature_bytes.as_slice())
+        .map_err(|e| SubmissionError::Malformed(e.to_string()))?;
+
+    signature
+        .verify_arbitrary_msg(message, &public_key)
+        .map_err(|_| SubmissionError::InvalidSignature)?;
+
+    Ok(public_key)
+}
+
+pub fn content_hash(content: &[u8]) -> [u8; 32] {
+    Sha256::digest(content).into()
+}
+
+/// Message signed by the worker: the request id and the hash of the submitted content.
+pub fn submission_message(request_id: u64, content_hash: &[u8; 32]) -> Vec<u8> {
+    let mut message = SUBMISSION_DOMAIN.to_vec();
+    message.extend_from_slice(&request_id.to_le_bytes());
+    message.extend_from_slice(content_hash);
+    message
+}
+
+/// Signs the submission of `content` for `request_id` with the account key.
+pub fn sign_submission(account: &LocalAccount, request_id: u64, content: &[u8]) -> SubmissionSignature {
+    sign_message(account, &submission_message(request_id, &content_hash(content)))
+}
+
+/// Checks the signature of a submission, returns the public key that signed it.
+pub fn verify_submission(
+    request_id: u64,
+    content: &[u8],
+    signature: &SubmissionSignature,
+) -> Result<Ed25519PublicKey, SubmissionError> {
+    verify_message(&submission_message(request_id, &content_hash(content)), signature)
+}
+
+/// Message signed by the worker to read the payload of `request_id`.
+pub fn payload_access_message(request_id: u64, nonce: &str) -> Vec<u8> {
+    let mut message = PAYLOAD_ACCESS_DOMAIN.to_vec();
+    message.extend_from_slice(&request_id.to_le_bytes());
+    message.extend_from_slice(nonce.as_bytes());
+    message
+}
+
+/// Answers the payload challenge of `request_id` with the account key.
+pub fn sign_payload_access(account: &LocalAccount, request_id: u64, nonce: &str) -> SubmissionSignature {
+    sign_message(account, &payload_access_message(request_id, nonce))
+}
+
+/// Checks the answer to a payload challenge, returns the public key that signed it.
+pub fn verify_payload_access(
+    request_id: u64,
+    nonce: &str,
+    signature: &SubmissionSignature,
+) -> Result<Ed25519PublicKey, SubmissionError> {
+    verify_message(&payload_access_message(request_id, nonce), signature)
+}
+
+fn encryption_key_message(encryption_key: &str, registered_at: u64) -> Vec<u8> {
+    let mut message = ENCRYPTION_KEY_DOMAIN.to_vec();
+    message.extend_from_slice(&registered_at.to_le_bytes());
+    message.extend_from_slice(encryption_key.as_bytes());
+    message
+}
+
+/// Registers the hex encoded x25519 `encryption_key` for the account, as of now.
+pub fn sign_encryption_key(account: &LocalAccount, encryption_key: &str) -> EncryptionKeyRegistration {
+    let registered_at = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+    let signature = sign_message(account, &encryption_key_message(encryption_key, registered_at));
+
+    EncryptionKeyRegistration {
+        address: account.address().to_hex_literal(),
+        encryption_key: encryption_key.to_owned(),
+        registered_at,
+        public_key: signature.public_key,
+        signature: signature.signature,
+    }
+}
+
+/// Checks the signature of a key registration, returns the public key that signed it.
+pub fn verify_encryption_key(
+    registration: &EncryptionKeyRegistration,
+) -> Result<Ed25519PublicKey, SubmissionError> {
+    verify_message(
+        &encryption_key_message(&registration.encryption_key, registration.registered_at),
+        &SubmissionSignature {
+            public_key: registration.public_key.to_owned(),
+            signature: registration.signature.to_owned(),
+        },
+    )
+}
+
+fn sealed_payload_message(request_id: u64, body: &[u8]) -> Vec<u8> {
+    let mut message = SEALED_PAYLOAD_DOMAIN.to_vec();
+    message.extend_from_slice(&request_id.to_le_bytes());
+    message.extend_from_slice(&content_hash(body));
+    message
+}
+
+/// Signs the upload of a sealed payload by the requester, `body` being the JSON of the `SealedPayload`.
+pub fn sign_sealed_payload(account: &LocalAccount, request_id: u64, body: &[u8]) -> SubmissionSignature {
+    sign_message(account, &sealed_payload_message(request_id, body))
+}
+
+/// Checks the signature of a sealed payload upload, returns the public key that signed it.
+pub fn verify_sealed_payload(
+    request_id: u64,
+    body: &[u8],
+    signature: &SubmissionSignature,
+) -> Result<Ed25519PublicKey, SubmissionError> {
+    verify_message(&sealed_payload_message(request_id, body), signature)
+}
+
+/// Address of the account created with `public_key`. Accounts that rotated
+/// their key must be checked against their on-chain authentication key instead.
+pub fn derived_address(public_key: &Ed25519PublicKey) -> AccountAddress {
+    AuthenticationKey::ed25519(public_key).account_address()
+}
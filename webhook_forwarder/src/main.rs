@@ -0,0 +1,231 @@
+mod outbox;
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use chain_listener::config::ListenerConfig;
+use chain_listener::event_source::{EventSource, GrpcEventSource, RestEventSource};
+use chain_listener::events::{DecodeError, EventEnvelope};
+use chain_listener::events_listener::run_envelope_listener_with_source;
+use dotenv::dotenv;
+use hmac::{Hmac, Mac};
+use proxirun_sdk::constants::CONTRACT_MODULE;
+use sha2::Sha256;
+use tokio::sync::{mpsc, Notify};
+use tokio::time::timeout;
+
+use outbox::{now_ms, Cursor, Delivery, Outbox};
+
+const INDEXER_URL: &str = "https://grpc.testnet.aptoslabs.com";
+const TESTNET_NODE: &str = "https://fullnode.testnet.aptoslabs.com";
+
+const EVENT_QUEUE_SIZE: usize = 1024;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+const POLL_INTERVAL: Duration = Duration::from_secs(1);
+const BASE_RETRY_DELAY_MS: u64 = 1_000;
+const MAX_RETRY_DELAY_MS: u64 = 10 * 60 * 1_000;
+const MAX_ATTEMPTS: u32 = 20;
+
+const SIGNATURE_HEADER: &str = "X-ProxiRun-Signature";
+const TIMESTAMP_HEADER: &str = "X-ProxiRun-Timestamp";
+const DELIVERY_HEADER: &str = "X-ProxiRun-Delivery";
+
+/// Hex encoded HMAC-SHA256 of `{timestamp}.{body}`, binding the signature to the time it was sent
+fn sign(secret: &[u8], timestamp: u64, body: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts keys of any size");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn retry_delay_ms(attempts: u32) -> u64 {
+    BASE_RETRY_DELAY_MS
+        .saturating_mul(1 << attempts.min(20))
+        .min(MAX_RETRY_DELAY_MS)
+}
+
+async fn deliver(client: &reqwest::Client, secret: &[u8], delivery: &Delivery) -> bool {
+    let timestamp = now_ms() / 1_000;
+    let response = client
+        .post(&delivery.url)
+        .header("Content-Type", "application/json")
+        .header(DELIVERY_HEADER, &delivery.id)
+        .header(TIMESTAMP_HEADER, timestamp)
+        .header(
+            SIGNATURE_HEADER,
+            format!("sha256={}", sign(secret, timestamp, &delivery.body)),
+        )
+        .body(delivery.body.to_owned())
+        .timeout(REQUEST_TIMEOUT)
+        .send()
+        .await;
+
+    match response {
+        Ok(response) if response.status().is_success() => true,
+        Ok(response) => {
+            println!(
+                "Delivery {} to {} failed. Status: {}",
+                delivery.id,
+                delivery.url,
+                response.status()
+            );
+            false
+        }
+        Err(e) => {
+            println!("Delivery {} to {} failed: {}", delivery.id, delivery.url, e);
+            false
+        }
+    }
+}
+
+/// Sends every due delivery of the outbox to `url`, rescheduling the failed ones with exponential backoff.
+async fn flush_outbox(outbox: &Outbox, client: &reqwest::Client, secret: &[u8], url: &str) {
+    let deliveries = match outbox.pending(url).await {
+        Ok(deliveries) => deliveries,
+        Err(e) => {
+            println!("Failed to read outbox: {}", e);
+            return;
+        }
+    };
+
+    for mut delivery in deliveries {
+        if delivery.next_attempt_ms > now_ms() {
+            continue;
+        }
+
+        let res = if deliver(client, secret, &delivery).await {
+            outbox.remove(&delivery.id).await
+        } else {
+            delivery.attempts += 1;
+            if delivery.attempts >= MAX_ATTEMPTS {
+                println!("Delivery {} gave up after {} attempts", delivery.id, delivery.attempts);
+                outbox.mark_failed(&delivery.id).await
+            } else {
+                delivery.next_attempt_ms = now_ms() + retry_delay_ms(delivery.attempts);
+                outbox.save(&delivery).await
+            }
+        };
+
+        if let Err(e) = res {
+            println!("Failed to update delivery {}: {}", delivery.id, e);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    dotenv().ok();
+    // without an indexer auth token, fall back to polling the fullnode
+    let auth_token = std::env::var("INDEXER_AUTH_KEY").ok();
+    let listener_config = ListenerConfig::from_env()?;
+    let webhook_urls: Vec<String> = std::env::var("WEBHOOK_URLS")
+        .expect("WEBHOOK_URLS must be set.")
+        .split(',')
+        .map(|url| url.trim().to_owned())
+        .filter(|url| !url.is_empty())
+        .collect();
+    let webhook_secret = std::env::var("WEBHOOK_SECRET").expect("WEBHOOK_SECRET must be set.");
+    let outbox_dir = std::env::var("OUTBOX_DIR").unwrap_or_else(|_| "./outbox".to_owned());
+
+    println!("Forwarding contract events to {} endpoints", webhook_urls.len());
+
+    let outbox = Arc::new(Outbox::open(&outbox_dir).await?);
+    let secret = Arc::new(webhook_secret.into_bytes());
+    let client = reqwest::Client::new();
+
+    // each endpoint has its own delivery task, so that a slow or dead endpoint only delays itself.
+    // deliveries left from a previous run are picked up by the first flush
+    let mut endpoints = vec![];
+    for url in webhook_urls {
+        let new_deliveries = Arc::new(Notify::new());
+        let task_outbox = outbox.clone();
+        let task_new_deliveries = new_deliveries.clone();
+        let task_client = client.clone();
+        let task_secret = secret.clone();
+        let task_url = url.to_owned();
+        tokio::spawn(async move {
+            loop {
+                flush_outbox(&task_outbox, &task_client, &task_secret, &task_url).await;
+                let _ = timeout(POLL_INTERVAL, task_new_deliveries.notified()).await;
+            }
+        });
+        endpoints.push((url, new_deliveries));
+    }
+
+    // resume after the last event put in the outbox, the events of its transaction that were
+    // already queued are skipped
+    let mut cursor = outbox.cursor().await?;
+    let start_version = cursor.map(|cursor| cursor.version);
+    match start_version {
+        Some(version) => println!("Resuming from version {}", version),
+        None => println!("No cursor found: starting from the chain tip"),
+    }
+
+    let (sender_events, mut receiver_events) = mpsc::channel::<EventEnvelope>(EVENT_QUEUE_SIZE);
+
+    // events that could not be decoded are logged and skipped
+    let (sender_decode_errors, mut receiver_decode_errors) =
+        mpsc::unbounded_channel::<DecodeError>();
+    tokio::spawn(async move {
+        while let Some(e) = receiver_decode_errors.recv().await {
+            println!("{}, raw event: {}", e, e.event.data);
+        }
+    });
+
+    let source: Box<dyn EventSource> = match (auth_token, start_version) {
+        (Some(auth_token), Some(version)) => Box::new(
+            GrpcEventSource::new(&auth_token, INDEXER_URL)
+                .with_config(listener_config)
+                .starting_from(version),
+        ),
+        (Some(auth_token), None) => Box::new(
+            GrpcEventSource::new(&auth_token, INDEXER_URL).with_config(listener_config),
+        ),
+        (None, start_version) => {
+            println!("INDEXER_AUTH_KEY not set: polling the fullnode for events");
+            let source = RestEventSource::new(TESTNET_NODE);
+            Box::new(match start_version {
+                Some(version) => source.starting_from(version),
+                None => source,
+            })
+        }
+    };
+    run_envelope_listener_with_source(
+        source,
+        CONTRACT_MODULE.to_owned(),
+        sender_events,
+        sender_decode_errors,
+    )
+    .await?;
+
+    // events already queued by the previous run, in the transaction it stopped at
+    let resume = cursor;
+    let mut skipped = 0;
+    while let Some(e) = receiver_events.recv().await {
+        if let Some(resume) = resume {
+            if e.version == resume.version && skipped < resume.events {
+                skipped += 1;
+                continue;
+            }
+        }
+
+        let body = serde_json::to_string(&e.event)?;
+
+        // persist before delivering, so that no event is lost on a crash
+        for (url, _) in &endpoints {
+            outbox.push(url, &body).await?;
+        }
+        let next = cursor.get_or_insert_with(Cursor::default);
+        next.advance(e.version);
+        outbox.save_cursor(next).await?;
+
+        for (_, new_deliveries) in &endpoints {
+            new_deliveries.notify_one();
+        }
+    }
+
+    println!("Chain listener has stopped, exiting");
+
+    Ok(())
+}
@@ -0,0 +1,144 @@
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+
+/// A webhook call waiting to be delivered to one endpoint.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Delivery {
+    pub id: String,
+    pub url: String,
+    pub body: String,
+    pub attempts: u32,
+    /// Unix time in milliseconds before which the delivery is not retried
+    pub next_attempt_ms: u64,
+}
+
+/// Position of the forwarder in the chain: every event up to the `events` first ones of
+/// transaction `version` is in the outbox.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    pub version: u64,
+    pub events: u64,
+}
+
+impl Cursor {
+    /// Moves the cursor past an event of transaction `version`.
+    pub fn advance(&mut self, version: u64) {
+        if version == self.version {
+            self.events += 1;
+        } else {
+            *self = Cursor { version, events: 1 };
+        }
+    }
+}
+
+const CURSOR_FILE: &str = "cursor";
+
+/// Deliveries persisted as one JSON file each, so that they survive restarts.
+/// Deliveries that ran out of attempts are moved to the `failed` subdirectory.
+pub struct Outbox {
+    dir: PathBuf,
+    failed_dir: PathBuf,
+    sequence: AtomicU64,
+}
+
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64
+}
+
+impl Outbox {
+    pub async fn open(dir: &str) -> std::io::Result<Self> {
+        let dir = PathBuf::from(dir);
+        let failed_dir = dir.join("failed");
+        fs::create_dir_all(&failed_dir).await?;
+
+        Ok(Outbox {
+            dir,
+            failed_dir,
+            sequence: AtomicU64::new(0),
+        })
+    }
+
+    /// Queues a new delivery, due immediately.
+    pub async fn push(&self, url: &str, body: &str) -> std::io::Result<Delivery> {
+        // ids sort in creation order
+        let id = format!(
+            "{:016}-{:08}",
+            now_ms(),
+            self.sequence.fetch_add(1, Ordering::Relaxed)
+        );
+        let delivery = Delivery {
+            id,
+            url: url.to_owned(),
+            body: body.to_owned(),
+            attempts: 0,
+            next_attempt_ms: 0,
+        };
+        self.save(&delivery).await?;
+
+        Ok(delivery)
+    }
+
+    pub async fn save(&self, delivery: &Delivery) -> std::io::Result<()> {
+        write_atomic(&self.path(&delivery.id), &serde_json::to_vec(delivery)?).await
+    }
+
+    /// Cursor saved by the previous run, `None` on the first run.
+    pub async fn cursor(&self) -> std::io::Result<Option<Cursor>> {
+        match fs::read(self.dir.join(CURSOR_FILE)).await {
+            Ok(cursor) => Ok(Some(serde_json::from_slice(&cursor)?)),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub async fn save_cursor(&self, cursor: &Cursor) -> std::io::Result<()> {
+        write_atomic(&self.dir.join(CURSOR_FILE), &serde_json::to_vec(cursor)?).await
+    }
+
+    pub async fn remove(&self, id: &str) -> std::io::Result<()> {
+        fs::remove_file(self.path(id)).await
+    }
+
+    pub async fn mark_failed(&self, id: &str) -> std::io::Result<()> {
+        fs::rename(self.path(id), self.failed_dir.join(format!("{}.json", id))).await
+    }
+
+    /// Every delivery to `url` in the outbox, oldest first.
+    pub async fn pending(&self, url: &str) -> std::io::Result<Vec<Delivery>> {
+        let mut deliveries = vec![];
+        let mut entries = fs::read_dir(&self.dir).await?;
+        while let Some(entry) = entries.next_entry().await? {
+            let path = entry.path();
+            if path.extension().and_then(|e| e.to_str()) != Some("json") {
+                continue;
+            }
+
+            match serde_json::from_slice::<Delivery>(&fs::read(&path).await?) {
+                Ok(delivery) if delivery.url == url => deliveries.push(delivery),
+                Ok(_) => (),
+                Err(e) => println!("Skipping unreadable delivery {}: {}", path.display(), e),
+            }
+        }
+        deliveries.sort_by(|a, b| a.id.cmp(&b.id));
+
+        Ok(deliveries)
+    }
+
+    fn path(&self, id: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", id))
+    }
+}
+
+// write then rename, a crash never leaves a truncated file behind
+async fn write_atomic(path: &Path, content: &[u8]) -> std::io::Result<()> {
+    let tmp_path = path.with_extension("tmp");
+    fs::write(&tmp_path, content).await?;
+    fs::rename(tmp_path, path).await
+}
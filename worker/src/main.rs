@@ -6,21 +6,33 @@ use proxirun_sdk::constants::{CONTRACT_ADDRESS, CONTRACT_MODULE, MODULE_IDENTIFI
 use rand::rngs::OsRng;
 use rand::Rng;
 use reqwest::Client as ReqwestClient;
+use reqwest::StatusCode;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::sync::Arc;
+use std::time::Duration;
 use tokio::fs;
 use tokio::sync::{mpsc, Mutex};
 use tokio::task::JoinSet;
+use tokio::time::sleep;
 use fal_rust::{
     client::{ClientCredentials, FalClient},
     utils::download_image,
 };
 
 
-use chain_listener::events_listener::run_listener;
-use proxirun_sdk::events::{ContractEvent, OnBidWon, OnNewWorkRequest};
+use chain_listener::config::ListenerConfig;
+use chain_listener::event_source::{EventSource, GrpcEventSource, RestEventSource};
+use chain_listener::events::DecodeError;
+use chain_listener::metrics::serve_metrics;
+use chain_listener::subscriber::ChainListener;
+use proxirun_sdk::events::{OnBidWon, OnNewWorkRequest};
 use proxirun_sdk::orchestrator::{AspectRatio, TaskDefinition, TaskPayload, TextGenerationSettings};
+use proxirun_sdk::encryption::{EncryptionKey, PayloadEnvelope};
+use proxirun_sdk::submission::{
+    sign_encryption_key, sign_payload_access, sign_submission, PayloadChallenge, NONCE_HEADER,
+    PUBLIC_KEY_HEADER, SIGNATURE_HEADER,
+};
 
 use dotenv::dotenv;
 
@@ -37,16 +49,85 @@ struct FalOutput {
 
 
 const INDEXER_URL: &'static str = "https://grpc.testnet.aptoslabs.com";
+const EVENT_BUFFER_SIZE: usize = 1024;
 
 const TESTNET_NODE: &'static str = "https://fullnode.testnet.aptoslabs.com";
 const FAUCET_URL: &'static str = "https://faucet.testnet.aptoslabs.com";
 
+// the requester may take a while to seal the payload once the auction is over
+const PAYLOAD_MAX_TRIES: usize = 30;
+const PAYLOAD_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+/// Registers the key requesters seal their payloads to, signed with the worker account key.
+async fn register_encryption_key(
+    orchestrator_url: &str,
+    account: &LocalAccount,
+    encryption_key: &EncryptionKey,
+) -> Result<(), reqwest::Error> {
+    let registration = sign_encryption_key(account, &encryption_key.public_key());
+    ReqwestClient::new()
+        .post(format!("{}/workers/encryption-key", orchestrator_url))
+        .json(&registration)
+        .send()
+        .await?
+        .error_for_status()?;
+
+    Ok(())
+}
+
+/// Reads the task payload, proving with a signed challenge that this worker won the auction.
+/// Sealed payloads are decrypted locally.
+async fn fetch_task_payload(
+    orchestrator_url: &str,
+    request_id: u64,
+    account: &LocalAccount,
+    encryption_key: &EncryptionKey,
+) -> Result<TaskPayload, Box<dyn std::error::Error + Send + Sync>> {
+    let client = ReqwestClient::new();
+    let mut try_id = 0;
+    loop {
+        try_id += 1;
+
+        let challenge: PayloadChallenge = client
+            .get(format!("{}/request-payload/{}/challenge", orchestrator_url, request_id))
+            .send()
+            .await?
+            .error_for_status()?
+            .json()
+            .await?;
+        let signature = sign_payload_access(account, request_id, &challenge.nonce);
+
+        let response = client
+            .get(format!("{}/request-payload/{}", orchestrator_url, request_id))
+            .header(PUBLIC_KEY_HEADER, signature.public_key)
+            .header(SIGNATURE_HEADER, signature.signature)
+            .header(NONCE_HEADER, challenge.nonce)
+            .send()
+            .await?;
+
+        // the orchestrator may not have seen the OnBidWon event or the sealed payload yet
+        if response.status() == StatusCode::CONFLICT && try_id < PAYLOAD_MAX_TRIES {
+            sleep(PAYLOAD_RETRY_DELAY).await;
+            continue;
+        }
+
+        return match response.error_for_status()?.json().await? {
+            PayloadEnvelope::Plain(task_payload) => Ok(task_payload),
+            PayloadEnvelope::Sealed(sealed) => Ok(encryption_key.open(request_id, &sealed)?),
+        };
+    }
+}
+
 async fn upload_generated(
     content: Vec<u8>,
     orchestrator_url: &str,
     endpoint: &str,
     request_id: u64,
+    account: &LocalAccount,
 ) -> bool {
+    // the orchestrator only accepts submissions signed by the auction winner
+    let signature = sign_submission(account, request_id, &content);
+
     // submit to orchestrator
     let client = ReqwestClient::new();
     let file_part = reqwest::multipart::Part::bytes(content)
@@ -57,6 +138,8 @@ async fn upload_generated(
     let target_url = format!("{}/{}/{}", orchestrator_url, endpoint, request_id);
     let response = client
         .post(target_url)
+        .header(PUBLIC_KEY_HEADER, signature.public_key)
+        .header(SIGNATURE_HEADER, signature.signature)
         .multipart(form)
         .send()
         .await
@@ -78,7 +161,11 @@ async fn upload_generated(
 #[tokio::main(flavor = "multi_thread")]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     dotenv().ok();
-    let auth_token = std::env::var("INDEXER_AUTH_KEY").expect("INDEXER_AUTH_KEY must be set.");
+    // without an indexer auth token, fall back to polling the fullnode
+    let auth_token = std::env::var("INDEXER_AUTH_KEY").ok();
+    let listener_config = ListenerConfig::from_env()?;
+    // optional, e.g. 127.0.0.1:9100, to expose the listener metrics to Prometheus
+    let metrics_addr = std::env::var("METRICS_ADDR").ok();
     let orchestrator_url =
         std::env::var("ORCHESTRATOR_URL").expect("ORCHESTRATOR_URL must be set.");
     let orchestrator_port =
@@ -96,7 +183,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     //let account = LocalAccount::from_private_key(PRIVATE_KEY, 0)?;
 
     let mut rng = OsRng::default();
-    let account = LocalAccount::generate(&mut rng);
+    let account = Arc::new(LocalAccount::generate(&mut rng));
     let account_address = account.address();
 
     println!(
@@ -110,13 +197,17 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         .await
         .unwrap();
 
+    // requesters can seal their payloads to this key once this worker wins their auction
+    let encryption_key = EncryptionKey::generate();
+    register_encryption_key(&full_orchestrator_url, &account, &encryption_key).await?;
+
     let mut task_set = JoinSet::new();
 
-    // create channels for communication
-    let (sender_events, mut receiver_events) = mpsc::unbounded_channel::<ContractEvent>();
-    let (sender_new_work_request, mut receiver_new_work_request) =
-        mpsc::unbounded_channel::<OnNewWorkRequest>();
-    let (sender_on_bid_won, mut receiver_on_bid_won) = mpsc::unbounded_channel::<OnBidWon>();
+    // subscribe to the events of interest, before the listener starts
+    let listener = ChainListener::new(EVENT_BUFFER_SIZE);
+    let mut new_work_requests = listener.subscribe::<OnNewWorkRequest>();
+    let worker_address = account_address.to_string();
+    let mut won_bids = listener.subscribe_filtered(move |e: &OnBidWon| e.winner == worker_address);
 
     //
     let task_records: Arc<Mutex<HashMap<u64, TaskDefinition>>> =
@@ -125,8 +216,9 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // start the service to handle new work requests
     let clone = task_records.clone();
     let cloned_url = full_orchestrator_url.clone();
+    let bidding_account = account.clone();
     task_set.spawn(async move {
-        while let Some(req) = receiver_new_work_request.recv().await {
+        while let Some(req) = new_work_requests.recv().await {
             println!("New auction with request_id: {}", req.request_id);
 
             // fetch work details from server
@@ -155,7 +247,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             let res = proxirun_sdk::contract_interact::bid(
                 req.request_id,
                 chosen_price,
-                &account,
+                &bidding_account,
                 &rest_client,
             )
             .await
@@ -167,7 +259,6 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let clone = task_records.clone();
     task_set.spawn(async move {
         let cloned_url = full_orchestrator_url.clone(); //.as_str();
-        let task_payload_base_url = format!("{}/{}/", &cloned_url, "request-payload");
 
         // set up worker externals
         let openai = {
@@ -177,12 +268,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             ))
         };
         let fal = Arc::new(FalClient::new(ClientCredentials::Key(fal_token)));
-        while let Some(req) = receiver_on_bid_won.recv().await {
-            // check if is winner of the auction
-            if req.winner != account_address.to_string() {
-                continue;
-            }
-
+        while let Some(req) = won_bids.recv().await {
             println!("Won auction with request_id: {}", req.request_id);
             let task_definition = {
                 let lock = clone.lock().await;
@@ -190,11 +276,19 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             };
 
             // need to query the payloads for generation
-            let task_payload_target_url = format!("{}{}", task_payload_base_url, req.request_id);
-            let res = reqwest::get(task_payload_target_url).await.unwrap();
-            let task_payload: TaskPayload = res.json().await.unwrap();
+            let task_payload =
+                match fetch_task_payload(&cloned_url, req.request_id, &account, &encryption_key)
+                    .await
+                {
+                    Ok(task_payload) => task_payload,
+                    Err(e) => {
+                        println!("Request {} - Failed to read payload: {}", req.request_id, e);
+                        continue;
+                    }
+                };
 
             let temp_url = cloned_url.clone();
+            let task_account = account.clone();
             let openai_client = openai.clone();
             let fal_client = fal.clone();
             // then process the work
@@ -234,9 +328,13 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
                             // submit to orchestrator
                             // Send the text payload to the server using a POST request
+                            let signature =
+                                sign_submission(&task_account, req.request_id, message.as_bytes());
                             let client = ReqwestClient::new();
                             let response = client
                                 .post(&format!("{}/submit-text/{}", temp_url, req.request_id))
+                                .header(PUBLIC_KEY_HEADER, signature.public_key)
+                                .header(SIGNATURE_HEADER, signature.signature)
                                 .body(message)
                                 .send()
                                 .await
@@ -298,6 +396,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 temp_url.as_str(),
                                 "submit-image",
                                 req.request_id,
+                                &task_account,
                             )
                             .await;
                         } else {
@@ -317,6 +416,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
                                 temp_url.as_str(),
                                 "submit-voice",
                                 req.request_id,
+                                &task_account,
                             )
                             .await;
                         } else {
@@ -331,28 +431,34 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
         }
     });
 
-    // start chain listener
+    // events that could not be decoded are logged and skipped
+    let (sender_decode_errors, mut receiver_decode_errors) =
+        mpsc::unbounded_channel::<DecodeError>();
     task_set.spawn(async move {
-        run_listener(
-            &auth_token,
-            INDEXER_URL,
-            CONTRACT_MODULE.to_owned(),
-            sender_events,
-        )
-        .await
-        .unwrap();
+        while let Some(e) = receiver_decode_errors.recv().await {
+            println!("{}, raw event: {}", e, e.event.data);
+        }
     });
 
+    // start chain listener
     task_set.spawn(async move {
-        while let Some(e) = receiver_events.recv().await {
-            match e {
-                ContractEvent::OnNewWorkRequest(data) => {
-                    sender_new_work_request.send(data).unwrap();
-                }
-                ContractEvent::OnBidWon(data) => {
-                    sender_on_bid_won.send(data).unwrap();
-                }
-                _ => (), // ignore other events
+        let source: Box<dyn EventSource> = match auth_token {
+            Some(auth_token) => Box::new(
+                GrpcEventSource::new(&auth_token, INDEXER_URL).with_config(listener_config),
+            ),
+            None => {
+                println!("INDEXER_AUTH_KEY not set: polling the fullnode for events");
+                Box::new(RestEventSource::new(TESTNET_NODE))
+            }
+        };
+        let listener_stats = listener
+            .run(source, CONTRACT_MODULE.to_owned(), sender_decode_errors)
+            .await
+            .unwrap();
+
+        if let Some(metrics_addr) = metrics_addr {
+            if let Err(e) = serve_metrics(&metrics_addr, listener_stats).await {
+                println!("Metrics server stopped: {}", e);
             }
         }
     });